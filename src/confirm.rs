@@ -0,0 +1,27 @@
+//! Yes/no confirmations before risky operations.
+//!
+//! A confirmation is only ever asked on a terminal. Without one (a pipe, a
+//! CI job) the answer is "no", so a gated operation refuses instead of
+//! waiting for input that will never come; `--yes` answers "yes" up front.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks `question` on stderr and reads the answer from stdin.
+///
+/// Returns true only for `y` or `yes` (in any case). Returns false without
+/// asking when stdin is not a terminal.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    stdin.lock().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
@@ -23,3 +23,15 @@ pub const ALLOWED_EXTENSIONS: &[&str] = &[
 
 /// Buffer size for file I/O operations (64KB)
 pub const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Bytes copied between checkpoints in resumable mode (64MB)
+pub const CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Number of leading bytes inspected when detecting encoding and line endings (8KB)
+pub const SNIFF_SIZE: usize = 8 * 1024;
+
+/// Lines of context shown on each side of a non-top insertion in dry-run mode
+pub const PREVIEW_CONTEXT_LINES: usize = 3;
+
+/// Environment variable holding default text when no text argument is given
+pub const TEXT_ENV_VAR: &str = "PREPEND_TEXT";
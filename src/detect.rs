@@ -0,0 +1,223 @@
+//! Detection helpers for encodings and line endings.
+//!
+//! Detection only looks at a prefix of the file (see [`SNIFF_SIZE`]), so it
+//! stays cheap on large files. [`inspect`] gathers all of it in one call.
+
+use crate::constants::SNIFF_SIZE;
+use crate::error::PrependError;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Text encoding detected from a file prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 (or plain ASCII) without a byte order mark
+    Utf8,
+    /// UTF-8 with a byte order mark
+    Utf8Bom,
+    /// UTF-16 little endian (with BOM)
+    Utf16Le,
+    /// UTF-16 big endian (with BOM)
+    Utf16Be,
+    /// Not valid text; contains NUL bytes or invalid UTF-8
+    Binary,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Binary => "binary",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Line-ending style detected from a file prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Unix style `\n`
+    Lf,
+    /// Windows style `\r\n`
+    Crlf,
+    /// Both styles are present
+    Mixed,
+    /// No line breaks found
+    None,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+            LineEnding::Mixed => "mixed",
+            LineEnding::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Everything [`inspect`] found out about a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInspection {
+    /// Size in bytes
+    pub size: u64,
+    /// Whether the content looks binary rather than like text
+    pub is_binary: bool,
+    /// Detected encoding
+    pub encoding: Encoding,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+    /// The style used by most line breaks in the prefix;
+    /// [`LineEnding::Mixed`] on a tie and [`LineEnding::None`] without any
+    pub dominant_line_ending: LineEnding,
+    /// Whether the last line ends in a line break
+    pub ends_with_newline: bool,
+}
+
+/// Inspects the file at `path`.
+///
+/// Only the metadata, the sniffing prefix (see [`SNIFF_SIZE`]) and the last
+/// character are read. Line breaks in UTF-16 files are recognized as such.
+///
+/// # Returns
+///
+/// * `Ok(FileInspection)` - The detected attributes
+/// * `Err(PrependError)` - The file could not be opened or read
+pub fn inspect(path: &Path) -> Result<FileInspection, PrependError> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut prefix = Vec::with_capacity(SNIFF_SIZE);
+    (&mut file)
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut prefix)?;
+    let encoding = detect_encoding(&prefix);
+
+    let unit = match encoding {
+        Encoding::Utf16Le | Encoding::Utf16Be => 2,
+        _ => 1,
+    };
+    let mut last = vec![0; size.min(unit) as usize];
+    file.seek(SeekFrom::End(-(last.len() as i64)))?;
+    file.read_exact(&mut last)?;
+
+    Ok(FileInspection {
+        size,
+        is_binary: encoding == Encoding::Binary,
+        encoding,
+        bom: matches!(
+            encoding,
+            Encoding::Utf8Bom | Encoding::Utf16Le | Encoding::Utf16Be
+        ),
+        dominant_line_ending: dominant_line_ending(&code_units(&prefix, encoding)),
+        ends_with_newline: code_units(&last, encoding).ends_with(b"\n"),
+    })
+}
+
+/// Returns the style used by most line breaks in `prefix`.
+///
+/// Unlike [`detect_line_ending`], a file with a few stray endings of the
+/// other style still gets the style it mostly uses; [`LineEnding::Mixed`]
+/// is only returned on a tie.
+pub fn dominant_line_ending(prefix: &[u8]) -> LineEnding {
+    let (lf, crlf) = count_line_endings(prefix);
+    if lf + crlf == 0 {
+        return LineEnding::None;
+    }
+    match lf.cmp(&crlf) {
+        std::cmp::Ordering::Greater => LineEnding::Lf,
+        std::cmp::Ordering::Less => LineEnding::Crlf,
+        std::cmp::Ordering::Equal => LineEnding::Mixed,
+    }
+}
+
+/// Narrows UTF-16 code units to single bytes, so the line-ending helpers
+/// can look for `\r` and `\n`; other encodings are returned as they are.
+fn code_units(bytes: &[u8], encoding: Encoding) -> Vec<u8> {
+    let decode = match encoding {
+        Encoding::Utf16Le => u16::from_le_bytes,
+        Encoding::Utf16Be => u16::from_be_bytes,
+        _ => return bytes.to_vec(),
+    };
+    bytes
+        .chunks_exact(2)
+        .map(|pair| u8::try_from(decode([pair[0], pair[1]])).unwrap_or(u8::MAX))
+        .collect()
+}
+
+/// Reads up to `limit` bytes from the start of a file.
+pub fn read_prefix(path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(limit);
+    File::open(path)?
+        .take(limit as u64)
+        .read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// Detects the encoding of a file from its leading bytes.
+///
+/// A multi-byte character cut off at the end of the prefix is not treated as
+/// invalid, so a [`SNIFF_SIZE`] prefix of a larger UTF-8 file is still UTF-8.
+pub fn detect_encoding(prefix: &[u8]) -> Encoding {
+    if prefix.starts_with(b"\xEF\xBB\xBF") {
+        return Encoding::Utf8Bom;
+    }
+    if prefix.starts_with(b"\xFF\xFE") {
+        return Encoding::Utf16Le;
+    }
+    if prefix.starts_with(b"\xFE\xFF") {
+        return Encoding::Utf16Be;
+    }
+    if prefix.contains(&0) {
+        return Encoding::Binary;
+    }
+    match std::str::from_utf8(prefix) {
+        Ok(_) => Encoding::Utf8,
+        // error_len() is None when the input merely ends mid-character
+        Err(e) if e.error_len().is_none() => Encoding::Utf8,
+        Err(_) => Encoding::Binary,
+    }
+}
+
+/// Detects the line-ending style used in `prefix`.
+pub fn detect_line_ending(prefix: &[u8]) -> LineEnding {
+    match count_line_endings(prefix) {
+        (0, 0) => LineEnding::None,
+        (_, 0) => LineEnding::Lf,
+        (0, _) => LineEnding::Crlf,
+        _ => LineEnding::Mixed,
+    }
+}
+
+/// Counts the `\n` and `\r\n` line breaks in `prefix`.
+fn count_line_endings(prefix: &[u8]) -> (usize, usize) {
+    let mut lf = 0;
+    let mut crlf = 0;
+    for (i, &byte) in prefix.iter().enumerate() {
+        if byte == b'\n' {
+            if i > 0 && prefix[i - 1] == b'\r' {
+                crlf += 1;
+            } else {
+                lf += 1;
+            }
+        }
+    }
+    (lf, crlf)
+}
+
+/// Returns true if the file already starts with `header`.
+pub fn header_present(path: &Path, header: &[u8]) -> io::Result<bool> {
+    let prefix = read_prefix(path, header.len())?;
+    Ok(prefix == header)
+}
+
+/// Reads the sniffing prefix of a file (see [`SNIFF_SIZE`]).
+pub fn sniff(path: &Path) -> io::Result<Vec<u8>> {
+    read_prefix(path, SNIFF_SIZE)
+}
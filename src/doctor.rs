@@ -0,0 +1,268 @@
+//! The `doctor` subcommand: non-destructive diagnostics for a target file.
+//!
+//! Gathers everything usually asked for on an issue report (file type,
+//! permissions, encoding, line endings, whether the header is already there)
+//! into one report, available as text or JSON.
+
+use crate::constants::{BLUE, GREEN, RED, RESET};
+use crate::detect::{self, Encoding, LineEnding};
+use crate::error::PrependError;
+use crate::json::JsonObject;
+use crate::transform::NewlinePolicy;
+use crate::{
+    Config, OutputFormat, can_write, has_allowed_extension, option_value, projected_size,
+    special_file_kind, validate_target,
+};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Name of the probe file briefly created to test directory writability
+const PROBE_NAME: &str = ".prepend_doctor_probe";
+
+/// Parsed arguments of the `doctor` subcommand
+#[derive(Debug)]
+pub struct DoctorOptions {
+    /// File to inspect
+    pub path: PathBuf,
+    /// Optional text to check for at the start of the file
+    pub header: Option<String>,
+    /// Report format
+    pub format: OutputFormat,
+}
+
+/// Results of the doctor checks for one path
+#[derive(Debug)]
+pub struct DoctorReport {
+    /// The inspected path
+    pub path: PathBuf,
+    /// Whether the path exists
+    pub exists: bool,
+    /// Kind of filesystem entry: `file`, `directory`, `symlink`, `missing`,
+    /// the kind of a special file (e.g. `named pipe (FIFO)`) or `other`
+    pub file_type: &'static str,
+    /// Whether the caller may write the file
+    pub writable: bool,
+    /// Whether a temp file can be created next to it
+    pub directory_writable: bool,
+    /// Whether the extension is on the allowed list (or absent)
+    pub extension_allowed: bool,
+    /// Detected encoding, if the file could be read
+    pub encoding: Option<Encoding>,
+    /// Line-ending style used by most line breaks, if the file could be read
+    pub line_ending: Option<LineEnding>,
+    /// Whether the file already starts with the header, if one was given
+    pub header_present: Option<bool>,
+    /// Current size in bytes
+    pub size: Option<u64>,
+    /// Size after prepending the header (or the current size without one)
+    pub estimated_size: Option<u64>,
+    /// Why a real run would refuse the file, if it would
+    pub problem: Option<String>,
+}
+
+/// Parses the arguments following `doctor`.
+///
+/// # Arguments
+///
+/// * `args` - Arguments after the `doctor` subcommand name
+///
+/// # Returns
+///
+/// * `Ok(DoctorOptions)` - A path, an optional header and the report format
+/// * `Err(PrependError)` - Missing path or an invalid option value
+pub fn parse_doctor_arguments(args: &[String]) -> Result<DoctorOptions, PrependError> {
+    let mut positionals = Vec::new();
+    let mut format = OutputFormat::Text;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => format = option_value(&mut iter, arg)?.parse()?,
+            _ => positionals.push(arg.clone()),
+        }
+    }
+
+    let mut positionals = positionals.into_iter();
+    let path = positionals
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| PrependError::MissingValue("doctor".to_string()))?;
+
+    Ok(DoctorOptions {
+        path,
+        // Mirror argument mode, which uses the default newline policy
+        header: positionals
+            .next()
+            .map(|text| NewlinePolicy::Auto.apply_to_text(&text)),
+        format,
+    })
+}
+
+/// Runs every check against `path` without modifying it.
+///
+/// # Arguments
+///
+/// * `path` - File to inspect
+/// * `header` - Text that would be prepended, if known
+pub fn diagnose(path: &Path, header: Option<&str>) -> DoctorReport {
+    let metadata = fs::symlink_metadata(path).ok();
+    let file_type = match &metadata {
+        None => "missing",
+        Some(m) if m.file_type().is_symlink() => {
+            if path.is_file() {
+                "symlink"
+            } else {
+                "other"
+            }
+        }
+        Some(m) if m.is_file() => "file",
+        Some(m) if m.is_dir() => "directory",
+        Some(m) => special_file_kind(&m.file_type()).unwrap_or("other"),
+    };
+    let is_file = path.is_file();
+    // The same config a real run would use, so both judge the file alike
+    let config = Config {
+        filename: path.to_path_buf(),
+        prepend_text: header.unwrap_or_default().to_string(),
+        newline: NewlinePolicy::Auto,
+        ..Default::default()
+    };
+
+    let inspection = if is_file {
+        detect::inspect(path).ok()
+    } else {
+        None
+    };
+    let size = inspection.as_ref().map(|i| i.size);
+
+    DoctorReport {
+        path: path.to_path_buf(),
+        exists: metadata.is_some(),
+        file_type,
+        writable: is_file && can_write(path),
+        directory_writable: directory_writable(path),
+        extension_allowed: has_allowed_extension(path),
+        encoding: inspection.as_ref().map(|i| i.encoding),
+        line_ending: inspection.as_ref().map(|i| i.dominant_line_ending),
+        header_present: match header {
+            Some(h) if is_file => detect::header_present(path, h.as_bytes()).ok(),
+            _ => None,
+        },
+        size,
+        estimated_size: match header {
+            None => size,
+            Some(_) if is_file => projected_size(&config).ok(),
+            Some(_) => None,
+        },
+        problem: validate_target(&config).err().map(|e| e.to_string()),
+    }
+}
+
+/// Checks that a file can be created in the directory containing `path`.
+fn directory_writable(path: &Path) -> bool {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(PROBE_NAME);
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+impl DoctorReport {
+    /// Returns true if a prepend to this path is expected to succeed.
+    pub fn is_healthy(&self) -> bool {
+        self.problem.is_none() && self.directory_writable
+    }
+
+    /// Renders the report as human-readable text.
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}Prepend Doctor:{} {:?}\n", BLUE, RESET, self.path);
+        let mut row = |label: &str, value: String| {
+            out.push_str(&format!("  {:<20}{}\n", label, value));
+        };
+
+        row("exists:", yes_no(self.exists));
+        row("type:", self.file_type.to_string());
+        row("writable:", yes_no(self.writable));
+        row("directory writable:", yes_no(self.directory_writable));
+        row(
+            "extension:",
+            if self.extension_allowed {
+                "allowed".to_string()
+            } else {
+                "uncommon".to_string()
+            },
+        );
+        row("encoding:", display_or_unknown(self.encoding));
+        row("line endings:", display_or_unknown(self.line_ending));
+        if let Some(present) = self.header_present {
+            row("header present:", yes_no(present));
+        }
+        row("size:", bytes_or_unknown(self.size));
+        row("estimated result:", bytes_or_unknown(self.estimated_size));
+        if let Some(problem) = &self.problem {
+            row("problem:", problem.clone());
+        }
+
+        if self.is_healthy() {
+            out.push_str(&format!("{}OK:{} Ready for prepending.\n", GREEN, RESET));
+        } else {
+            out.push_str(&format!(
+                "{}PROBLEM:{} This file cannot be prepended to.\n",
+                RED, RESET
+            ));
+        }
+        out
+    }
+
+    /// Renders the report as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let mut obj = JsonObject::report()
+            .string("path", &self.path.to_string_lossy())
+            .bool("exists", self.exists)
+            .string("file_type", self.file_type)
+            .bool("writable", self.writable)
+            .bool("directory_writable", self.directory_writable)
+            .bool("extension_allowed", self.extension_allowed);
+        obj = match self.encoding {
+            Some(encoding) => obj.string("encoding", &encoding.to_string()),
+            None => obj.raw("encoding", "null".to_string()),
+        };
+        obj = match self.line_ending {
+            Some(ending) => obj.string("line_ending", &ending.to_string()),
+            None => obj.raw("line_ending", "null".to_string()),
+        };
+        obj = obj.optional_bool("header_present", self.header_present);
+        obj = match (self.size, self.estimated_size) {
+            (Some(size), Some(estimated)) => {
+                obj.number("size", size).number("estimated_size", estimated)
+            }
+            _ => obj
+                .raw("size", "null".to_string())
+                .raw("estimated_size", "null".to_string()),
+        };
+        obj = match &self.problem {
+            Some(problem) => obj.string("problem", problem),
+            None => obj.raw("problem", "null".to_string()),
+        };
+        obj.bool("healthy", self.is_healthy()).render()
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn display_or_unknown<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |v| v.to_string())
+}
+
+fn bytes_or_unknown(value: Option<u64>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |v| format!("{} bytes", v))
+}
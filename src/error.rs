@@ -1,5 +1,7 @@
 //! Custom error types for the prepend tool.
 
+use crate::constants::TEXT_ENV_VAR;
+use crate::warning::Warning;
 use std::fmt;
 use std::io;
 
@@ -12,12 +14,60 @@ pub enum PrependError {
     /// Path is not a regular file
     NotAFile(String),
 
+    /// Path is a FIFO, socket or device rather than a regular file (path, kind)
+    SpecialFile(String, &'static str),
+
     /// File is not writable
     NotWritable(String),
 
+    /// File extension is not allowed (`--require-extension`)
+    DisallowedExtension(String),
+
+    /// File is locked by another process
+    Locked(String),
+
     /// Input text is empty
     EmptyInput,
 
+    /// No text was given and stdin is not a terminal to prompt on
+    NoTextProvided,
+
+    /// Command-line option is missing its value
+    MissingValue(String),
+
+    /// Command-line option has an unsupported value (option, value)
+    InvalidValue(String, String),
+
+    /// More targets than `--max-files` allows (found, cap)
+    TooManyFiles(usize, usize),
+
+    /// A `--pairs` line is not `path<delimiter>text` (line number, line)
+    MalformedPair(usize, String),
+
+    /// Two options (or an option and a usage) that cannot be combined
+    ConflictingOptions(String, String),
+
+    /// `--limit-lines` would truncate the target in place
+    TruncationNotAllowed,
+
+    /// Prepend text does not end with a newline (strict newline mode)
+    MissingTrailingNewline,
+
+    /// No line matched the insertion pattern (file, pattern)
+    PatternNotFound(String, String),
+
+    /// Not enough free space for the temp file (file, required, available)
+    InsufficientSpace(String, u64, u64),
+
+    /// `--text-command` could not be run or exited non-zero (command, detail)
+    CommandFailed(String, String),
+
+    /// `--git-tracked` was given for a target outside any git repository
+    NotInGitRepository(String),
+
+    /// A warning was raised under `--strict`
+    WarningAsError(Warning),
+
     /// I/O error occurred
     Io(io::Error),
 }
@@ -27,8 +77,70 @@ impl fmt::Display for PrependError {
         match self {
             PrependError::FileNotFound(path) => write!(f, "File {} does not exist.", path),
             PrependError::NotAFile(path) => write!(f, "{} is not a regular file.", path),
+            PrependError::SpecialFile(path, kind) => write!(
+                f,
+                "{} is a {}, not a regular file. Its contents can't be rewritten, so there is nothing to prepend to.",
+                path, kind
+            ),
             PrependError::NotWritable(path) => write!(f, "File {} is not writable.", path),
+            PrependError::DisallowedExtension(path) => {
+                write!(f, "File {} does not have an allowed extension.", path)
+            }
+            PrependError::Locked(path) => {
+                write!(f, "File {} is locked by another process.", path)
+            }
             PrependError::EmptyInput => write!(f, "Input text is empty."),
+            PrependError::NoTextProvided => write!(
+                f,
+                "No text provided and stdin is not a terminal. Pass the text as an argument, pipe it in, or set {}.",
+                TEXT_ENV_VAR
+            ),
+            PrependError::MissingValue(option) => write!(f, "Option {} requires a value.", option),
+            PrependError::InvalidValue(option, value) => {
+                write!(f, "Invalid value '{}' for option {}.", value, option)
+            }
+            PrependError::TooManyFiles(found, cap) => write!(
+                f,
+                "Found {} target files, more than --max-files {}. Nothing was modified (--yes proceeds anyway).",
+                found, cap
+            ),
+            PrependError::MalformedPair(number, line) => write!(
+                f,
+                "Line {} of --pairs input is not 'path<delimiter>text': {:?}",
+                number, line
+            ),
+            PrependError::ConflictingOptions(first, second) => {
+                write!(f, "{} cannot be used with {}.", first, second)
+            }
+            PrependError::TruncationNotAllowed => write!(
+                f,
+                "--limit-lines would truncate the target in place. Use --output, or pass --allow-truncate."
+            ),
+            PrependError::MissingTrailingNewline => {
+                write!(f, "Prepend text does not end with a newline.")
+            }
+            PrependError::PatternNotFound(path, pattern) => {
+                write!(f, "No line in {} matches pattern '{}'.", path, pattern)
+            }
+            PrependError::InsufficientSpace(path, required, available) => write!(
+                f,
+                "Not enough free space for {}: needs {} bytes, {} available ({} short).",
+                path,
+                required,
+                available,
+                required - available
+            ),
+            PrependError::CommandFailed(command, detail) => {
+                write!(f, "Command '{}' failed: {}", command, detail)
+            }
+            PrependError::NotInGitRepository(path) => write!(
+                f,
+                "{} is not inside a git repository, so --git-tracked can't tell which files are tracked.",
+                path
+            ),
+            PrependError::WarningAsError(warning) => {
+                write!(f, "{} (--strict treats warnings as errors)", warning)
+            }
             PrependError::Io(err) => write!(f, "{}", err),
         }
     }
@@ -0,0 +1,111 @@
+//! Restricting targets to files tracked by git, for `--git-tracked`.
+//!
+//! Shells out to the `git` binary: `rev-parse --show-toplevel` finds the
+//! repository of each target's directory and `ls-files` lists what it
+//! tracks, once per repository.
+
+use crate::error::PrependError;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Splits `targets` into those tracked by git and those that aren't
+/// (untracked or ignored files, build output, ...).
+///
+/// Targets that can't be resolved (e.g. missing files) are kept, so that
+/// validation reports the actual problem.
+///
+/// # Returns
+///
+/// * `Ok((tracked, untracked))` - Both in their original order
+/// * `Err(PrependError)` - A target is not inside a git repository, or
+///   `git` could not be run
+pub fn filter_git_tracked(
+    targets: Vec<PathBuf>,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), PrependError> {
+    let mut roots: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut tracked_files: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+    let mut tracked = Vec::new();
+    let mut untracked = Vec::new();
+
+    for target in targets {
+        let Some(resolved) = resolve(&target).filter(|_| target.exists()) else {
+            tracked.push(target);
+            continue;
+        };
+        let dir = resolved.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let root = match roots.get(&dir) {
+            Some(root) => root.clone(),
+            None => {
+                let root = repository_root(&dir, &target)?;
+                roots.insert(dir, root.clone());
+                root
+            }
+        };
+        if !tracked_files.contains_key(&root) {
+            let files = tracked_in(&root)?;
+            tracked_files.insert(root.clone(), files);
+        }
+        if tracked_files[&root].contains(&resolved) {
+            tracked.push(target);
+        } else {
+            untracked.push(target);
+        }
+    }
+
+    Ok((tracked, untracked))
+}
+
+/// Returns the top level of the repository containing `dir`.
+fn repository_root(dir: &Path, target: &Path) -> Result<PathBuf, PrependError> {
+    let output = git(dir, &["rev-parse", "--show-toplevel"])?;
+    match output {
+        Some(stdout) => {
+            let root = String::from_utf8_lossy(&stdout).trim_end().to_string();
+            Ok(fs::canonicalize(&root).unwrap_or_else(|_| PathBuf::from(root)))
+        }
+        None => Err(PrependError::NotInGitRepository(format!("{:?}", target))),
+    }
+}
+
+/// Returns the canonical paths of every file tracked in the repository at
+/// `root`.
+fn tracked_in(root: &Path) -> Result<HashSet<PathBuf>, PrependError> {
+    let stdout = git(root, &["ls-files", "-z"])?
+        .ok_or_else(|| PrependError::NotInGitRepository(format!("{:?}", root)))?;
+    Ok(stdout
+        .split(|&b| b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let path = root.join(String::from_utf8_lossy(name).as_ref());
+            resolve(&path).unwrap_or(path)
+        })
+        .collect())
+}
+
+/// Makes `path` absolute with its directory canonicalized, but without
+/// following a symlink in the last component, so a tracked symlink is
+/// matched as itself rather than as the file it points to.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(parent).ok()?.join(path.file_name()?))
+}
+
+/// Runs `git` in `dir`, returning its stdout, or `None` if it failed (as it
+/// does outside a repository).
+fn git(dir: &Path, args: &[&str]) -> Result<Option<Vec<u8>>, PrependError> {
+    let command = format!("git {}", args.join(" "));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| PrependError::CommandFailed(command, e.to_string()))?;
+    Ok(output.status.success().then_some(output.stdout))
+}
@@ -0,0 +1,200 @@
+//! Inserting the text somewhere other than the top of the file.
+//!
+//! The original is streamed line by line, so memory use stays bounded by the
+//! longest line no matter how large the file is. Original lines are copied
+//! byte-for-byte, including their line endings.
+
+use crate::error::PrependError;
+use crate::preview;
+use regex::bytes::Regex;
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+/// Where the text is inserted
+#[derive(Debug, Clone, Default)]
+pub enum InsertPosition {
+    /// Before the first line of the file
+    #[default]
+    Top,
+    /// Before the first line matching the pattern
+    BeforePattern(Regex),
+    /// After the first line matching the pattern
+    AfterPattern(Regex),
+    /// After the `[name]` header of an INI-style section
+    UnderSection(String),
+}
+
+impl InsertPosition {
+    /// Returns the pattern of a pattern position
+    pub fn pattern(&self) -> Option<&Regex> {
+        match self {
+            InsertPosition::BeforePattern(pattern) | InsertPosition::AfterPattern(pattern) => {
+                Some(pattern)
+            }
+            InsertPosition::Top | InsertPosition::UnderSection(_) => None,
+        }
+    }
+}
+
+/// What to do when no line matches the pattern
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoMatch {
+    /// Append the text at the end of the file, with a warning
+    #[default]
+    Append,
+    /// Fail and leave the file unchanged
+    Error,
+}
+
+impl FromStr for NoMatch {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "append" => Ok(NoMatch::Append),
+            "error" => Ok(NoMatch::Error),
+            _ => Err(PrependError::InvalidValue(
+                "--no-match".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+/// Copies `reader` to `writer`, inserting `header` before (or, with
+/// `after`, after) the first line matching `pattern`.
+///
+/// The pattern is matched against each line without its line ending. When
+/// inserting after an unterminated last line, a `\n` is written first so the
+/// header starts on its own line. If no line matches and `no_match` is
+/// [`NoMatch::Append`], the header is written at the end in the same way.
+///
+/// Returns whether a line matched.
+pub(crate) fn copy_inserting_at(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    header: &[u8],
+    pattern: &Regex,
+    after: bool,
+    no_match: NoMatch,
+) -> io::Result<bool> {
+    copy_inserting_where(
+        reader,
+        writer,
+        header,
+        |line| pattern.is_match(line),
+        after,
+        no_match,
+    )
+}
+
+/// Copies `reader` to `writer`, inserting `header` right after the `[name]`
+/// line of the section called `section`.
+///
+/// Section headers are matched ignoring surrounding whitespace, so
+/// `  [ server ]` names the section `server`. If the section doesn't exist
+/// it is created, with the header under it, just above the first existing
+/// section; a file without sections gets it at the end. Either way any keys
+/// above the first section stay global.
+///
+/// The original is scanned once to look for the section before it is
+/// copied. Returns whether the section already existed.
+pub(crate) fn copy_under_section<R: BufRead + Seek>(
+    reader: &mut R,
+    writer: &mut impl Write,
+    header: &[u8],
+    section: &str,
+) -> io::Result<bool> {
+    let start = reader.stream_position()?;
+    let exists = contains_line(reader, |line| {
+        section_name(line) == Some(section.as_bytes())
+    })?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    if exists {
+        let is_section = |line: &[u8]| section_name(line) == Some(section.as_bytes());
+        copy_inserting_where(reader, writer, header, is_section, true, NoMatch::Error)?;
+    } else {
+        let created = preview::add_before(header, format!("[{}]\n", section).as_bytes());
+        let is_any_section = |line: &[u8]| section_name(line).is_some();
+        copy_inserting_where(
+            reader,
+            writer,
+            &created,
+            is_any_section,
+            false,
+            NoMatch::Append,
+        )?;
+    }
+    Ok(exists)
+}
+
+/// The line-matching core shared by the pattern and section inserts.
+fn copy_inserting_where(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    header: &[u8],
+    is_match: impl Fn(&[u8]) -> bool,
+    after: bool,
+    no_match: NoMatch,
+) -> io::Result<bool> {
+    let mut line = Vec::new();
+    let mut terminated = true;
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        terminated = line.ends_with(b"\n");
+        if is_match(strip_line_ending(&line)) {
+            if after {
+                writer.write_all(&line)?;
+                if !terminated {
+                    writer.write_all(b"\n")?;
+                }
+                writer.write_all(header)?;
+            } else {
+                writer.write_all(header)?;
+                writer.write_all(&line)?;
+            }
+            io::copy(reader, writer)?;
+            return Ok(true);
+        }
+        writer.write_all(&line)?;
+    }
+
+    if no_match == NoMatch::Append {
+        if !terminated {
+            writer.write_all(b"\n")?;
+        }
+        writer.write_all(header)?;
+    }
+    Ok(false)
+}
+
+/// Reads `reader` to the end (or the first match), reporting whether any line
+/// satisfies `is_match`.
+fn contains_line(reader: &mut impl BufRead, is_match: impl Fn(&[u8]) -> bool) -> io::Result<bool> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(false);
+        }
+        if is_match(strip_line_ending(&line)) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Returns the name of the section a `[name]` header line opens.
+fn section_name(line: &[u8]) -> Option<&[u8]> {
+    let inner = line.trim_ascii().strip_prefix(b"[")?.strip_suffix(b"]")?;
+    Some(inner.trim_ascii())
+}
+
+/// Returns `line` without its trailing `\n` or `\r\n`.
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
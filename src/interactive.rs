@@ -0,0 +1,95 @@
+//! The interactive text prompt.
+//!
+//! [`InteractiveReader`] takes its input and prompt streams as parameters,
+//! so the whole session can be driven from in-memory buffers instead of a
+//! terminal. `parse_arguments` runs it on stdin and stdout.
+
+use crate::constants::{BLUE, RESET, YELLOW};
+use crate::error::PrependError;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+
+/// Prompts for the prepend text and reads it until end of input
+pub struct InteractiveReader<R, W> {
+    input: R,
+    prompt: W,
+    lossy: bool,
+}
+
+impl<R: BufRead, W: Write> InteractiveReader<R, W> {
+    /// Creates a reader taking the text from `input` and writing the prompt
+    /// to `prompt`.
+    pub fn new(input: R, prompt: W) -> Self {
+        InteractiveReader {
+            input,
+            prompt,
+            lossy: false,
+        }
+    }
+
+    /// Replaces invalid UTF-8 with U+FFFD instead of failing (`--lossy`).
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Runs the session: prompts for `targets`, then reads the text.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The text exactly as entered; the newline policy is
+    ///   applied later, when the header is rendered
+    /// * `Err(PrependError)` - The input was empty or whitespace only, not
+    ///   valid UTF-8, or a stream failed
+    pub fn run(&mut self, targets: &[PathBuf]) -> Result<String, PrependError> {
+        self.write_prompt(targets)?;
+        read_text(&mut self.input, self.lossy)
+    }
+
+    /// Writes the banner naming the target(s) and how to finish the input.
+    pub fn write_prompt(&mut self, targets: &[PathBuf]) -> io::Result<()> {
+        match targets {
+            [target] => writeln!(
+                self.prompt,
+                "{}Prepend Tool:{} Ready to process {:?}",
+                BLUE, RESET, target
+            )?,
+            _ => writeln!(
+                self.prompt,
+                "{}Prepend Tool:{} Ready to process {} files",
+                BLUE,
+                RESET,
+                targets.len()
+            )?,
+        }
+        writeln!(
+            self.prompt,
+            "Enter text to prepend (Press {}Ctrl+D{} on a new line to finish):",
+            YELLOW, RESET
+        )?;
+        writeln!(
+            self.prompt,
+            "----------------------------------------------"
+        )?;
+        self.prompt.flush()
+    }
+}
+
+/// Reads `input` to the end as the prepend text.
+///
+/// Fails with [`PrependError::EmptyInput`] if there is nothing but whitespace.
+pub fn read_text(input: &mut impl Read, lossy: bool) -> Result<String, PrependError> {
+    let mut buffer = String::new();
+    if lossy {
+        // Best effort: invalid sequences become U+FFFD instead of failing
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        buffer = String::from_utf8_lossy(&bytes).into_owned();
+    } else {
+        input.read_to_string(&mut buffer)?;
+    }
+    if buffer.trim().is_empty() {
+        return Err(PrependError::EmptyInput);
+    }
+    Ok(buffer)
+}
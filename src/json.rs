@@ -0,0 +1,97 @@
+//! Minimal JSON rendering for machine-readable output.
+//!
+//! Output objects are small and flat, so a tiny builder is enough and keeps
+//! the crate free of serialization dependencies.
+
+use std::fmt::{Display, Write};
+
+/// Version of the JSON output format, emitted as `schema_version` in every
+/// top-level object.
+///
+/// Fields are only added within a major version; removing or changing the
+/// meaning of a field bumps it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Builder for a JSON object with fields in insertion order.
+#[derive(Debug, Default)]
+pub struct JsonObject {
+    fields: Vec<(String, String)>,
+}
+
+impl JsonObject {
+    /// Creates an empty object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a top-level report, starting with the `schema_version` field.
+    pub fn report() -> Self {
+        Self::new().number("schema_version", SCHEMA_VERSION)
+    }
+
+    /// Adds a string field.
+    pub fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, escape(value))
+    }
+
+    /// Adds a numeric field.
+    pub fn number(self, key: &str, value: impl Display) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    /// Adds a boolean field.
+    pub fn bool(self, key: &str, value: bool) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    /// Adds a boolean field, or `null` if the value is unknown.
+    pub fn optional_bool(self, key: &str, value: Option<bool>) -> Self {
+        match value {
+            Some(value) => self.bool(key, value),
+            None => self.raw(key, "null".to_string()),
+        }
+    }
+
+    /// Adds a field whose value is already rendered JSON.
+    pub fn raw(mut self, key: &str, json: String) -> Self {
+        self.fields.push((key.to_string(), json));
+        self
+    }
+
+    /// Renders the object on a single line.
+    pub fn render(&self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{}:{}", escape(key), value))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// Renders already-rendered JSON values as an array.
+pub fn array(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Quotes and escapes a string as a JSON string literal.
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
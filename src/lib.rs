@@ -3,21 +3,65 @@
 //! This library provides functionality to prepend text to the beginning of files
 //! using buffered I/O and atomic file operations to ensure data safety.
 
+pub mod confirm;
 pub mod constants;
+pub mod detect;
+pub mod doctor;
 pub mod error;
+pub mod git;
+pub mod insert;
+pub mod interactive;
+pub mod json;
+pub mod lock;
+pub mod managed;
+pub mod manifest;
+pub mod patch;
+pub mod plugin;
+pub mod present;
+pub mod preview;
+pub mod replace;
+pub mod resume;
+pub mod since;
+pub mod space;
+pub mod spec;
+pub mod status;
+pub mod template;
+pub mod transaction;
+pub mod transform;
+pub mod warning;
 
-use constants::{ALLOWED_EXTENSIONS, BLUE, BUFFER_SIZE, RESET, YELLOW};
+use constants::{ALLOWED_EXTENSIONS, BLUE, BUFFER_SIZE, RESET, TEXT_ENV_VAR};
 use error::PrependError;
+use insert::{InsertPosition, NoMatch};
+use interactive::InteractiveReader;
+use lock::LockMode;
+use manifest::ManifestFormat;
+use plugin::{ContentTransform, FileContext, LinesOnly, TransformedLines};
+use present::PresentMatch;
+use preview::{InsertionPreview, PreviewWriter};
+use replace::Replacement;
+use spec::Spec;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use template::TemplateValues;
+use transaction::Transaction;
+use transform::{EolStyle, Indent, IndentStyle, NewlinePolicy, TabConversion};
+use warning::{Strictness, Warning, WarningCode};
 
 /// Configuration for the prepend operation.
 ///
 /// Contains all the parameters needed to perform a prepend operation,
 /// including the target file, text to prepend, and execution mode.
+#[derive(Clone, Default)]
 pub struct Config {
     /// Path to the file to be modified
     pub filename: PathBuf,
@@ -25,6 +69,233 @@ pub struct Config {
     pub prepend_text: String,
     /// If true, show what would happen without modifying the file
     pub dry_run: bool,
+    /// In a dry run, show only the lines that would be inserted
+    /// (`--only-added`, which implies `--dry-run`)
+    pub only_added: bool,
+    /// If true, refuse to prepend text that does not end with a newline.
+    ///
+    /// `parse_arguments` already appends a newline in argument mode and
+    /// interactive mode, so this mainly guards library callers that build
+    /// a `Config` directly.
+    pub strict_newline: bool,
+    /// All target files collected from the command line.
+    ///
+    /// `filename` is the first of these. When more than one target is given,
+    /// the same text is prepended to each of them in turn.
+    pub targets: Vec<PathBuf>,
+    /// If set, write a unified diff of the change to this path instead of
+    /// modifying the targets
+    pub emit_patch: Option<PathBuf>,
+    /// If true, checkpoint progress so an interrupted prepend can be resumed
+    pub resumable: bool,
+    /// If set, write the result here instead of replacing `filename`
+    pub output: Option<PathBuf>,
+    /// If true, a missing target is created empty and the text written to
+    /// it (`--create`)
+    pub create: bool,
+    /// Permission bits for files the run creates, e.g. `0o755` (`--mode`,
+    /// Unix only); with `force` they are applied to existing files too
+    pub mode: Option<u32>,
+    /// If set, copy only the first N lines of the original after the text
+    pub limit_lines: Option<usize>,
+    /// Format of per-file result reports
+    pub format: OutputFormat,
+    /// Advisory locking of the target while it is rewritten
+    pub lock: LockMode,
+    /// If set, indent each non-blank line of the text
+    pub indent: Option<Indent>,
+    /// Whether fixed indentation uses spaces or tabs
+    pub indent_style: IndentStyle,
+    /// Normalize the text to a canonical form before other transforms
+    pub canonicalize: bool,
+    /// Where in the file the text is inserted
+    pub position: InsertPosition,
+    /// Fallback when `position` is a pattern that matches no line
+    pub no_match: NoMatch,
+    /// Report elapsed time and throughput for each file
+    pub time: bool,
+    /// Stop a batch at the first failed target instead of continuing
+    pub fail_fast: bool,
+    /// Refuse targets with an uncommon extension instead of warning
+    pub require_extension: bool,
+    /// Restore already-modified targets if any target in the batch fails
+    pub transactional: bool,
+    /// Refuse up front if the temp file would not fit in the free space
+    pub check_space: bool,
+    /// Write the copy in the system temp dir and only stage the finished
+    /// result next to the destination (`--fast-temp`)
+    pub fast_temp: bool,
+    /// Count the lines of the result while writing it
+    pub recount_lines: bool,
+    /// If set, only targets modified after this time are processed
+    pub modified_since: Option<SystemTime>,
+    /// Only process targets tracked by git (`--git-tracked`)
+    pub git_tracked: bool,
+    /// Leave empty (zero-byte) targets alone (`--skip-empty`)
+    pub skip_empty: bool,
+    /// Leave targets alone that already start with the text
+    /// (`--skip-if-present`)
+    pub skip_if_present: bool,
+    /// How `skip_if_present` compares the start of the file with the text
+    pub present_match: PresentMatch,
+    /// If set, end the text with a hash marker line after this comment
+    /// prefix, and replace or skip the block a previous run left
+    /// (`--hash-marker`, see [`managed`])
+    pub hash_marker: Option<String>,
+    /// Report where each target's temp file is created (`--print-temp-path`)
+    pub print_temp_path: bool,
+    /// If set, record every target and its outcome in this file
+    /// (`--write-manifest`)
+    pub manifest: Option<PathBuf>,
+    /// Format of the `manifest` file
+    pub manifest_format: ManifestFormat,
+    /// If set, report progress events as JSON lines on this already open
+    /// file descriptor (`--status-fd`, Unix only)
+    pub status_fd: Option<i32>,
+    /// Tabs/spaces conversion applied to the text after indentation
+    pub tabs: Option<TabConversion>,
+    /// How line breaks at the end of the text and of the result are handled
+    /// (see [`NewlinePolicy`]). The default uses the text as given;
+    /// `parse_arguments` picks [`NewlinePolicy::Auto`] unless `--raw` is set.
+    pub newline: NewlinePolicy,
+    /// If set, rewrite every line ending of the text and the original to
+    /// this style (`--normalize-eol`). Targets that don't look like UTF-8
+    /// text are left alone unless `force` is set
+    pub normalize_eol: Option<EolStyle>,
+    /// Go past the usual caution (`--force`): normalize line endings even in
+    /// targets that look binary, and apply `mode` to existing files
+    pub force: bool,
+    /// Whether warnings fail targets (or the run)
+    pub strict: Strictness,
+    /// Values for the `{date}`-style placeholders (`--template`); `None`
+    /// leaves the text as it is
+    pub template: Option<TemplateValues>,
+    /// Regex rewrites of the text, applied in order for each target after
+    /// the template placeholders (`--replace-regex`)
+    pub replacements: Vec<Replacement>,
+    /// Answer "yes" to every confirmation (`--yes`), e.g. going past
+    /// `--max-files`
+    pub assume_yes: bool,
+    /// Per-target texts from `--spec`; a target listed here gets its own
+    /// text instead of `prepend_text` (see [`Config::for_target`])
+    pub specs: Vec<Spec>,
+    /// Derives the temp path from the destination; [`temp_path_for`] if unset.
+    ///
+    /// The returned path must be in the same directory as the destination
+    /// (or at least on the same filesystem), otherwise the final rename is
+    /// no longer atomic.
+    pub temp_name_fn: Option<TempNameFn>,
+    /// Custom transforms, run in order after the built-in text options; see
+    /// [`Config::with_transform`]
+    pub transforms: Vec<Arc<dyn ContentTransform>>,
+}
+
+/// Custom temp file naming, see [`Config::temp_name_fn`]
+pub type TempNameFn = Arc<dyn Fn(&Path) -> PathBuf + Send + Sync>;
+
+impl Config {
+    /// Returns the file the result is written to: `output` if set, else `filename`.
+    pub fn destination(&self) -> &Path {
+        self.output.as_deref().unwrap_or(&self.filename)
+    }
+
+    /// Returns the temp path used while writing the destination.
+    pub fn temp_path(&self) -> PathBuf {
+        match &self.temp_name_fn {
+            Some(temp_name) => temp_name(self.destination()),
+            None => temp_path_for(self.destination()),
+        }
+    }
+
+    /// Sets a custom temp file naming function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prepend::Config;
+    /// use std::path::Path;
+    ///
+    /// // Hidden temp files, e.g. for a cleanup job that ignores dotfiles
+    /// let config = Config::default().with_temp_name(|path: &Path| {
+    ///     let name = path.file_name().unwrap_or_default().to_string_lossy();
+    ///     path.with_file_name(format!(".{}.tmp", name))
+    /// });
+    /// ```
+    pub fn with_temp_name(
+        mut self,
+        temp_name: impl Fn(&Path) -> PathBuf + Send + Sync + 'static,
+    ) -> Config {
+        self.temp_name_fn = Some(Arc::new(temp_name));
+        self
+    }
+
+    /// Adds a custom transform to the end of the pipeline.
+    ///
+    /// Line transforms need the line-by-line copy, so they can't be
+    /// combined with `resumable`, and aren't reflected in patches (the same
+    /// goes for `normalize_eol`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use prepend::Config;
+    /// use prepend::plugin::{ContentTransform, FileContext};
+    ///
+    /// /// Wraps the text in a C block comment
+    /// struct BlockComment;
+    ///
+    /// impl ContentTransform for BlockComment {
+    ///     fn transform_header(&self, header: &[u8], _context: &FileContext) -> Vec<u8> {
+    ///         [b"/* ", header.trim_ascii_end(), b" */\n"].concat()
+    ///     }
+    /// }
+    ///
+    /// let config = Config::default().with_transform(BlockComment);
+    /// ```
+    pub fn with_transform(mut self, transform: impl ContentTransform + 'static) -> Config {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Returns a copy of this configuration aimed at a single target.
+    ///
+    /// If `specs` has an entry for `path`, its text replaces `prepend_text`.
+    pub fn for_target(&self, path: &Path) -> Config {
+        let prepend_text = match self.specs.iter().find(|spec| spec.file == path) {
+            Some(spec) => spec.text.clone(),
+            None => self.prepend_text.clone(),
+        };
+        Config {
+            filename: path.to_path_buf(),
+            prepend_text,
+            ..self.clone()
+        }
+    }
+}
+
+/// Format of machine-facing output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored, human-readable text
+    #[default]
+    Text,
+    /// One JSON object per report
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(PrependError::InvalidValue(
+                "--format".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
 }
 
 /// Parses command-line arguments into a configuration.
@@ -40,8 +311,42 @@ pub struct Config {
 ///
 /// # Modes
 ///
-/// - **Interactive mode**: If only filename is provided, prompts for text input
+/// - **Interactive mode**: If only filename is provided, prompts for text input.
+///   When stdin is not a terminal the text is read from it without a prompt,
+///   and an empty stdin fails with [`PrependError::NoTextProvided`]
 /// - **Argument mode**: If filename and text are provided, uses the text argument
+/// - **Environment default**: If no text argument is given but `PREPEND_TEXT`
+///   is set and non-empty, its value is used as if it were the text argument
+///
+/// Interactive input must be valid UTF-8 unless `--lossy` is given, in which
+/// case invalid byte sequences are replaced with U+FFFD. The original bytes
+/// are lost, so `--lossy` is only suitable for best-effort headers.
+///
+/// The text is returned as read; its trailing newline is handled when it is
+/// written, according to [`Config::newline`]. That is
+/// [`NewlinePolicy::Auto`] unless `--newline` (or `--no-newline`) says
+/// otherwise.
+///
+/// With `--raw` the text is used byte-for-byte from whichever source it came
+/// from: no trailing newline is added and nothing is trimmed or converted
+/// ([`NewlinePolicy::Preserve`]), so `--raw` can't be combined with
+/// `--newline`, `--canonicalize-header`, the indent options or the tab
+/// conversions. This disables all text normalization; substitutions the
+/// caller asks for (`--template`, `--replace-regex`) still happen, and checks
+/// such as `--strict-newline` still apply.
+///
+/// `--limit-lines` requires `--output` (or `--allow-truncate`), since
+/// otherwise it would truncate the target in place.
+///
+/// `--indent`, `--indent-tabs` and `--indent-match` are applied per target
+/// when the text is written (see [`render_header`]), after
+/// `--canonicalize-header`.
+///
+/// When two or more positionals are given, the last one is the text and all
+/// others are target files. Every argument after `--` is a positional. With
+/// `--text-stdin` all positionals are targets and the text is read from stdin
+/// without a prompt; `--text-command` likewise takes it from a command's
+/// output. Glob patterns are expanded unless `--no-glob` is set.
 ///
 /// # Examples
 ///
@@ -51,88 +356,678 @@ pub struct Config {
 /// let config = parse_arguments(&args).unwrap();
 /// ```
 pub fn parse_arguments(args: &[String]) -> Result<Config, PrependError> {
-    let mut filename = None;
-    let mut text_arg = None;
+    parse_arguments_confirming(args, |_, _| false)
+}
+
+/// Parses command-line arguments like [`parse_arguments`], asking
+/// `confirm_more` whether to go ahead when more targets than `--max-files`
+/// are found.
+///
+/// `confirm_more` gets the number of targets found and the cap, and is only
+/// called without `--yes`. If it returns false, parsing fails with
+/// [`PrependError::TooManyFiles`]. Everything is parsed once either way, so
+/// stdin is only read once and the template values are captured once.
+pub fn parse_arguments_confirming(
+    args: &[String],
+    confirm_more: impl FnOnce(usize, usize) -> bool,
+) -> Result<Config, PrependError> {
+    let mut positionals = Vec::new();
     let mut dry_run = false;
+    let mut strict_newline = false;
+    let mut no_glob = false;
+    let mut emit_patch = None;
+    let mut lossy = false;
+    let mut raw = false;
+    let mut resumable = false;
+    let mut output = None;
+    let mut create = false;
+    let mut mode = None;
+    let mut limit_lines = None;
+    let mut allow_truncate = false;
+    let mut format = OutputFormat::Text;
+    let mut lock = LockMode::None;
+    let mut indent = None;
+    let mut indent_style = IndentStyle::Spaces;
+    let mut canonicalize = false;
+    let mut position = InsertPosition::Top;
+    let mut no_match = NoMatch::Append;
+    let mut time = false;
+    let mut fail_fast = false;
+    let mut text_stdin = false;
+    let mut text_command = None;
+    let mut require_extension = false;
+    let mut transactional = false;
+    let mut check_space = false;
+    let mut fast_temp = false;
+    let mut recount_lines = false;
+    let mut specs = Vec::new();
+    let mut modified_since = None;
+    let mut git_tracked = false;
+    let mut max_files = None;
+    let mut strict = Strictness::Off;
+    let mut template = None;
+    let mut replacements = Vec::new();
+    let mut newline = None;
+    let mut normalize_eol = None;
+    let mut force = false;
+    let mut tabs = None;
+    let mut print_temp_path = false;
+    let mut manifest = None;
+    let mut manifest_format = ManifestFormat::Json;
+    let mut status_fd = None;
+    let mut only_added = false;
+    let mut skip_if_present = false;
+    let mut skip_empty = false;
+    let mut present_match = PresentMatch::Exact;
+    let mut hash_marker = None;
+    let mut assume_yes = false;
+    let mut pairs = false;
+    let mut pairs_delimiter = '\t';
     let mut show_help = false;
 
     // Skip executable name
-    for arg in args.iter().skip(1) {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
         match arg.as_str() {
             "--dry-run" => dry_run = true,
-            "--help" | "-h" => show_help = true,
-            _ => {
-                if filename.is_none() {
-                    filename = Some(PathBuf::from(arg));
-                } else if text_arg.is_none() {
-                    text_arg = Some(arg.clone());
+            "--only-added" => {
+                only_added = true;
+                dry_run = true;
+            }
+            "--strict-newline" => strict_newline = true,
+            "--no-glob" => no_glob = true,
+            "--lossy" => lossy = true,
+            "--raw" => raw = true,
+            "--newline" => newline = Some(option_value(&mut iter, arg)?.parse()?),
+            "--no-newline" => newline = Some(NewlinePolicy::None),
+            "--normalize-eol" => {
+                normalize_eol = Some(option_value(&mut iter, arg)?.parse()?);
+            }
+            "--force" => force = true,
+            "--resumable" => resumable = true,
+            "--output" | "-o" => output = Some(PathBuf::from(option_value(&mut iter, arg)?)),
+            "--create" => create = true,
+            "--mode" => mode = Some(parse_mode(&option_value(&mut iter, arg)?)?),
+            "--limit-lines" => {
+                let value = option_value(&mut iter, arg)?;
+                let limit = value
+                    .parse()
+                    .map_err(|_| PrependError::InvalidValue(arg.clone(), value))?;
+                limit_lines = Some(limit);
+            }
+            "--allow-truncate" => allow_truncate = true,
+            "--format" => format = option_value(&mut iter, arg)?.parse()?,
+            "--lock" => lock = LockMode::Fail,
+            "--lock-wait" => lock = LockMode::Wait,
+            "--skip-locked" => lock = LockMode::Skip,
+            "--indent" => {
+                let value = option_value(&mut iter, arg)?;
+                let width = value
+                    .parse()
+                    .map_err(|_| PrependError::InvalidValue(arg.clone(), value))?;
+                indent = Some(Indent::Fixed(width));
+            }
+            "--indent-match" => indent = Some(Indent::MatchFirstLine),
+            "--indent-tabs" => indent_style = IndentStyle::Tabs,
+            "--canonicalize-header" => canonicalize = true,
+            "--expand-tabs" => {
+                let value = option_value(&mut iter, arg)?;
+                let width = value
+                    .parse()
+                    .ok()
+                    .filter(|&width| width > 0)
+                    .ok_or_else(|| PrependError::InvalidValue(arg.clone(), value))?;
+                tabs = Some(TabConversion::Expand(width));
+            }
+            "--unexpand" => tabs = Some(TabConversion::Unexpand(transform::DEFAULT_TAB_WIDTH)),
+            "--before-pattern" | "--after-pattern" => {
+                let value = option_value(&mut iter, arg)?;
+                let pattern = regex::bytes::Regex::new(&value)
+                    .map_err(|_| PrependError::InvalidValue(arg.clone(), value))?;
+                position = if arg == "--after-pattern" {
+                    InsertPosition::AfterPattern(pattern)
+                } else {
+                    InsertPosition::BeforePattern(pattern)
+                };
+            }
+            "--under-section" => {
+                let value = option_value(&mut iter, arg)?;
+                if value.trim().is_empty() {
+                    return Err(PrependError::InvalidValue(arg.clone(), value));
                 }
+                position = InsertPosition::UnderSection(value.trim().to_string());
+            }
+            "--no-match" => no_match = option_value(&mut iter, arg)?.parse()?,
+            "--time" => time = true,
+            "--print-temp-path" => print_temp_path = true,
+            "--write-manifest" => manifest = Some(PathBuf::from(option_value(&mut iter, arg)?)),
+            "--manifest-format" => manifest_format = option_value(&mut iter, arg)?.parse()?,
+            "--status-fd" => {
+                let value = option_value(&mut iter, arg)?;
+                let fd = value
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|fd| *fd >= 0)
+                    .ok_or_else(|| PrependError::InvalidValue(arg.clone(), value))?;
+                status_fd = Some(fd);
+            }
+            "--skip-if-present" => skip_if_present = true,
+            "--skip-empty" => skip_empty = true,
+            "--present-match" => {
+                present_match = option_value(&mut iter, arg)?.parse()?;
+                skip_if_present = true;
+            }
+            "--hash-marker" => {
+                let value = option_value(&mut iter, arg)?;
+                if value.trim().is_empty() || value.contains(['\n', '\r']) {
+                    return Err(PrependError::InvalidValue(arg.clone(), value));
+                }
+                hash_marker = Some(value);
+            }
+            "--fail-fast" => fail_fast = true,
+            "--keep-going" => fail_fast = false,
+            "--text-stdin" => text_stdin = true,
+            "--text-command" => text_command = Some(option_value(&mut iter, arg)?),
+            "--require-extension" => require_extension = true,
+            "--transactional" => transactional = true,
+            "--check-space" => check_space = true,
+            "--fast-temp" => fast_temp = true,
+            "--recount-lines" => recount_lines = true,
+            "--modified-since" => {
+                let value = option_value(&mut iter, arg)?;
+                modified_since = Some(since::parse_cutoff(&value, SystemTime::now())?);
+            }
+            "--git-tracked" => git_tracked = true,
+            "--max-files" => {
+                let value = option_value(&mut iter, arg)?;
+                let cap = value
+                    .parse()
+                    .map_err(|_| PrependError::InvalidValue(arg.clone(), value))?;
+                max_files = Some(cap);
+            }
+            // Captured once here, so every target gets the same values
+            "--template" => template = Some(TemplateValues::at(SystemTime::now())),
+            "--replace-regex" => replacements.push(option_value(&mut iter, arg)?.parse()?),
+            "--strict" => strict = Strictness::Fail,
+            "--strict-at-end" => strict = Strictness::FailAtEnd,
+            "--yes" | "--assume-yes" | "-y" => assume_yes = true,
+            "--pairs" => pairs = true,
+            "--pairs-delimiter" => {
+                let value = option_value(&mut iter, arg)?;
+                let mut chars = value.chars();
+                pairs_delimiter = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(PrependError::InvalidValue(arg.clone(), value)),
+                };
             }
+            "--spec" => specs.push(option_value(&mut iter, arg)?.parse::<Spec>()?),
+            "--emit-patch" => emit_patch = Some(PathBuf::from(option_value(&mut iter, arg)?)),
+            "--help" | "-h" => show_help = true,
+            // Everything after it is a target or the text, even `doctor`
+            "--" => positionals.extend(iter.by_ref().cloned()),
+            _ => positionals.push(arg.clone()),
         }
     }
 
-    if show_help || filename.is_none() {
+    if show_help || (positionals.is_empty() && specs.is_empty() && !pairs) {
         print_help(&args[0]);
         process::exit(0);
     }
 
-    let target_file = filename.unwrap();
+    if pairs {
+        let input = read_stdin_text(lossy)?;
+        specs.extend(spec::parse_pairs(&input, pairs_delimiter)?);
+    }
+    if !specs.is_empty() && !positionals.is_empty() {
+        let option = if pairs { "--pairs" } else { "--spec" };
+        return Err(PrependError::ConflictingOptions(
+            option.to_string(),
+            "positional arguments".to_string(),
+        ));
+    }
+
+    // With two or more positionals the last one is the text; everything before it is a target.
+    // --text-stdin and --text-command make every positional a target.
+    let text_arg = if positionals.len() > 1 && !text_stdin && text_command.is_none() {
+        positionals.pop()
+    } else {
+        None
+    };
+    let targets = if specs.is_empty() {
+        expand_targets(&positionals, no_glob)
+    } else {
+        specs.iter().map(|spec| spec.file.clone()).collect()
+    };
+    let target_file = targets[0].clone();
+
+    // Checked before anything is read or written, so a runaway glob costs nothing
+    if let Some(cap) = max_files
+        && targets.len() > cap
+        && !assume_yes
+        && !confirm_more(targets.len(), cap)
+    {
+        return Err(PrependError::TooManyFiles(targets.len(), cap));
+    }
+
+    if output.is_some() && targets.len() > 1 {
+        return Err(PrependError::ConflictingOptions(
+            "--output".to_string(),
+            "multiple target files".to_string(),
+        ));
+    }
+    // A resumed run never sees the part copied before the interruption
+    if recount_lines && resumable {
+        return Err(PrependError::ConflictingOptions(
+            "--recount-lines".to_string(),
+            "--resumable".to_string(),
+        ));
+    }
+    if limit_lines.is_some() && resumable {
+        return Err(PrependError::ConflictingOptions(
+            "--limit-lines".to_string(),
+            "--resumable".to_string(),
+        ));
+    }
+    // A patch run modifies nothing, so there is nothing to record
+    if manifest.is_some() && emit_patch.is_some() {
+        return Err(PrependError::ConflictingOptions(
+            "--write-manifest".to_string(),
+            "--emit-patch".to_string(),
+        ));
+    }
+    // Restoring would have to delete the output rather than roll it back
+    if transactional && output.is_some() {
+        return Err(PrependError::ConflictingOptions(
+            "--transactional".to_string(),
+            "--output".to_string(),
+        ));
+    }
+    if text_stdin && text_command.is_some() {
+        return Err(PrependError::ConflictingOptions(
+            "--text-stdin".to_string(),
+            "--text-command".to_string(),
+        ));
+    }
+    // These rely on the text sitting at the very top of the file
+    let pattern_option = match position {
+        InsertPosition::Top => None,
+        InsertPosition::BeforePattern(_) => Some("--before-pattern"),
+        InsertPosition::AfterPattern(_) => Some("--after-pattern"),
+        InsertPosition::UnderSection(_) => Some("--under-section"),
+    };
+    if let Some(pattern_option) = pattern_option {
+        let conflicting = [
+            (skip_if_present, "--skip-if-present"),
+            (hash_marker.is_some(), "--hash-marker"),
+            (resumable, "--resumable"),
+            (limit_lines.is_some(), "--limit-lines"),
+            (emit_patch.is_some(), "--emit-patch"),
+        ];
+        if let Some((_, option)) = conflicting.iter().find(|(set, _)| *set) {
+            return Err(PrependError::ConflictingOptions(
+                pattern_option.to_string(),
+                option.to_string(),
+            ));
+        }
+    }
+    // --indent-tabs would only have its tabs expanded again
+    if indent_style == IndentStyle::Tabs && matches!(tabs, Some(TabConversion::Expand(_))) {
+        return Err(PrependError::ConflictingOptions(
+            "--indent-tabs".to_string(),
+            "--expand-tabs".to_string(),
+        ));
+    }
+    // --raw already means "exactly as given"
+    if raw {
+        let conflicting = [
+            (newline.is_some(), "--newline"),
+            (canonicalize, "--canonicalize-header"),
+            (matches!(indent, Some(Indent::Fixed(_))), "--indent"),
+            (
+                matches!(indent, Some(Indent::MatchFirstLine)),
+                "--indent-match",
+            ),
+            (indent_style == IndentStyle::Tabs, "--indent-tabs"),
+            (
+                matches!(tabs, Some(TabConversion::Expand(_))),
+                "--expand-tabs",
+            ),
+            (
+                matches!(tabs, Some(TabConversion::Unexpand(_))),
+                "--unexpand",
+            ),
+        ];
+        if let Some((_, option)) = conflicting.iter().find(|(set, _)| *set) {
+            return Err(PrependError::ConflictingOptions(
+                "--raw".to_string(),
+                option.to_string(),
+            ));
+        }
+    }
+    let newline = newline.unwrap_or(if raw {
+        NewlinePolicy::Preserve
+    } else {
+        NewlinePolicy::Auto
+    });
+    if strict_newline && newline == NewlinePolicy::None {
+        return Err(PrependError::ConflictingOptions(
+            "--strict-newline".to_string(),
+            "--newline none".to_string(),
+        ));
+    }
+    // The resumable copy never revisits the end of the file
+    if resumable && newline == NewlinePolicy::Force {
+        return Err(PrependError::ConflictingOptions(
+            "--resumable".to_string(),
+            "--newline force".to_string(),
+        ));
+    }
+    // The patch only adds the text, so it can't show capped lines going
+    // missing or a final newline being added
+    if emit_patch.is_some() {
+        let conflicting = [
+            (limit_lines.is_some(), "--limit-lines"),
+            (newline == NewlinePolicy::Force, "--newline force"),
+        ];
+        if let Some((_, option)) = conflicting.iter().find(|(set, _)| *set) {
+            return Err(PrependError::ConflictingOptions(
+                option.to_string(),
+                "--emit-patch".to_string(),
+            ));
+        }
+    }
+    // Both copy the original as it is, so rewritten line endings would go
+    // missing and a replaced managed block would stay
+    let rewriting = [
+        (normalize_eol.is_some(), "--normalize-eol"),
+        (hash_marker.is_some(), "--hash-marker"),
+    ];
+    if let Some((_, rewrite)) = rewriting.iter().find(|(set, _)| *set) {
+        let conflicting = [
+            (resumable, "--resumable"),
+            (emit_patch.is_some(), "--emit-patch"),
+        ];
+        if let Some((_, option)) = conflicting.iter().find(|(set, _)| *set) {
+            return Err(PrependError::ConflictingOptions(
+                rewrite.to_string(),
+                option.to_string(),
+            ));
+        }
+    }
+    // A preview reads the target, and a missing one has nothing to show
+    if create && dry_run {
+        return Err(PrependError::ConflictingOptions(
+            "--create".to_string(),
+            "--dry-run".to_string(),
+        ));
+    }
+    // The resumable copy is renamed into place by the resume code
+    if mode.is_some() && resumable {
+        return Err(PrependError::ConflictingOptions(
+            "--mode".to_string(),
+            "--resumable".to_string(),
+        ));
+    }
+    // The checkpointed temp file has to survive next to the target
+    if resumable && fast_temp {
+        return Err(PrependError::ConflictingOptions(
+            "--resumable".to_string(),
+            "--fast-temp".to_string(),
+        ));
+    }
+    // Capping lines in place would silently throw the rest of the file away
+    if limit_lines.is_some() && output.is_none() && !allow_truncate {
+        return Err(PrependError::TruncationNotAllowed);
+    }
     let final_text;
 
-    if let Some(txt) = text_arg {
+    // An explicit argument wins over the environment default
+    let text_arg = text_arg.or_else(|| env::var(TEXT_ENV_VAR).ok().filter(|t| !t.is_empty()));
+
+    if let Some(spec) = specs.first() {
+        // Mode 5: every target brings its own text
+        final_text = spec.text.clone();
+    } else if let Some(command) = &text_command {
+        // Mode 4: output of a command
+        final_text = command_text(command, lossy)?;
+    } else if text_stdin {
+        // Mode 3: explicitly piped text, so no prompt
+        final_text = read_stdin_text(lossy)?;
+    } else if let Some(txt) = text_arg {
         // Mode 2: Argument
-        final_text = format!("{}\n", txt); // Ensure newline
+        final_text = txt;
     } else {
         // Mode 1: Interactive
-        println!(
-            "{}Prepend Tool:{} Ready to process {:?}",
-            BLUE, RESET, target_file
-        );
-        println!(
-            "Enter text to prepend (Press {}Ctrl+D{} on a new line to finish):",
-            YELLOW, RESET
-        );
-        println!("----------------------------------------------");
-        let mut buffer = String::new();
-        io::stdin().read_to_string(&mut buffer)?;
-        if buffer.trim().is_empty() {
-            return Err(PrependError::EmptyInput);
-        }
-        // Ensure the input ends with a newline so it doesn't merge with the first line of the file
-        if !buffer.ends_with('\n') {
-            buffer.push('\n');
+        if io::stdin().is_terminal() {
+            final_text = InteractiveReader::new(io::stdin().lock(), io::stdout())
+                .lossy(lossy)
+                .run(&targets)?;
+        } else {
+            // Piped text needs no prompt, and an empty pipe (e.g. CI) means no
+            // text was given at all
+            final_text = match read_stdin_text(lossy) {
+                Err(PrependError::EmptyInput) => return Err(PrependError::NoTextProvided),
+                result => result?,
+            };
         }
-        final_text = buffer;
     }
 
     Ok(Config {
         filename: target_file,
         prepend_text: final_text,
         dry_run,
+        only_added,
+        strict_newline,
+        targets,
+        emit_patch,
+        resumable,
+        output,
+        create,
+        mode,
+        limit_lines,
+        format,
+        lock,
+        indent,
+        indent_style,
+        canonicalize,
+        position,
+        no_match,
+        time,
+        fail_fast,
+        require_extension,
+        transactional,
+        check_space,
+        fast_temp,
+        recount_lines,
+        modified_since,
+        git_tracked,
+        skip_empty,
+        skip_if_present,
+        present_match,
+        hash_marker,
+        print_temp_path,
+        manifest,
+        manifest_format,
+        status_fd,
+        tabs,
+        newline,
+        normalize_eol,
+        force,
+        strict,
+        template,
+        replacements,
+        assume_yes,
+        specs,
+        temp_name_fn: None,
+        transforms: Vec::new(),
     })
 }
 
+/// Parses a `--mode` value: octal permission bits such as `755` or `0644`.
+fn parse_mode(value: &str) -> Result<u32, PrependError> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777 && !value.starts_with('+'))
+        .ok_or_else(|| PrependError::InvalidValue("--mode".to_string(), value.to_string()))
+}
+
+/// Reads the prepend text from stdin.
+///
+/// Fails with [`PrependError::EmptyInput`] if there is nothing but whitespace.
+fn read_stdin_text(lossy: bool) -> Result<String, PrependError> {
+    interactive::read_text(&mut io::stdin().lock(), lossy)
+}
+
+/// Runs `command` through the platform shell and returns its stdout as text.
+///
+/// This runs an arbitrary command with the user's privileges, exactly as if
+/// it had been typed into a shell. stderr is passed through.
+fn command_text(command: &str, lossy: bool) -> Result<String, PrependError> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let output = shell
+        .arg(command)
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::inherit())
+        .output()
+        .map_err(|e| PrependError::CommandFailed(command.to_string(), e.to_string()))?;
+    if !output.status.success() {
+        return Err(PrependError::CommandFailed(
+            command.to_string(),
+            output.status.to_string(),
+        ));
+    }
+
+    let text = if lossy {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        String::from_utf8(output.stdout)
+            .map_err(|e| PrependError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?
+    };
+    if text.trim().is_empty() {
+        return Err(PrependError::EmptyInput);
+    }
+    Ok(text)
+}
+
+/// Takes the value following an option that requires one.
+pub(crate) fn option_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, PrependError> {
+    iter.next()
+        .cloned()
+        .ok_or_else(|| PrependError::MissingValue(option.to_string()))
+}
+
+/// Expands glob patterns in target path arguments.
+///
+/// Shells on Unix expand `*.txt` before the program sees it, but `cmd.exe`
+/// and PowerShell usually don't. To behave the same everywhere, an argument
+/// containing `*`, `?` or `[` that does not exist as a literal path is
+/// expanded here.
+///
+/// # Arguments
+///
+/// * `paths` - Target path arguments as given on the command line
+/// * `no_glob` - If true, every argument is taken literally
+///
+/// # Returns
+///
+/// The resolved target paths, in argument order. A pattern that matches
+/// nothing (or is malformed) is kept as-is so validation can report it.
+pub fn expand_targets(paths: &[String], no_glob: bool) -> Vec<PathBuf> {
+    let mut targets = Vec::new();
+
+    for arg in paths {
+        let is_pattern = arg.contains(['*', '?', '[']);
+        if no_glob || !is_pattern || Path::new(arg).exists() {
+            targets.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let matches: Vec<PathBuf> = match glob::glob(arg) {
+            Ok(paths) => paths.filter_map(Result::ok).collect(),
+            Err(_) => Vec::new(),
+        };
+        if matches.is_empty() {
+            targets.push(PathBuf::from(arg));
+        } else {
+            targets.extend(matches);
+        }
+    }
+
+    targets
+}
+
+/// Removes targets that resolve to the same physical file.
+///
+/// Paths are compared by their canonical form, which collapses symlinks and
+/// `.`/`..` components, so overlapping globs or a symlink next to its target
+/// only receive the text once per run. Paths that cannot be canonicalized
+/// (e.g. missing files) are compared as given.
+///
+/// # Arguments
+///
+/// * `targets` - Target paths in processing order
+///
+/// # Returns
+///
+/// A tuple of the unique targets (first occurrence wins, order preserved)
+/// and the duplicates that were dropped.
+pub fn dedup_targets(targets: &[PathBuf]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut seen = HashSet::new();
+    let mut unique = Vec::new();
+    let mut duplicates = Vec::new();
+
+    for target in targets {
+        let key = fs::canonicalize(target).unwrap_or_else(|_| target.clone());
+        if seen.insert(key) {
+            unique.push(target.clone());
+        } else {
+            duplicates.push(target.clone());
+        }
+    }
+
+    (unique, duplicates)
+}
+
 /// Validates that a file exists, is a regular file, and is writable.
 ///
 /// # Arguments
 ///
 /// * `path` - Path to the file to validate
+/// * `require_extension` - Reject files outside [`ALLOWED_EXTENSIONS`]
+///   instead of warning about them
 ///
 /// # Returns
 ///
-/// * `Ok(())` - File is valid and ready for prepending
+/// * `Ok(Vec<Warning>)` - File is valid and ready for prepending, with any
+///   non-fatal warnings
 /// * `Err(PrependError)` - File validation failed
 ///
 /// # Warnings
 ///
-/// Prints a warning to stdout if the file has an uncommon extension,
-/// but does not fail validation.
-pub fn validate_file(path: &Path) -> Result<(), PrependError> {
+/// Unless `require_extension` is set, an uncommon extension produces a
+/// [`WarningCode::UncommonExtension`] warning but does not fail validation.
+/// Nothing is printed; reporting warnings is up to the caller.
+pub fn validate_file(path: &Path, require_extension: bool) -> Result<Vec<Warning>, PrependError> {
     if !path.exists() {
         return Err(PrependError::FileNotFound(format!("{:?}", path)));
     }
     if !path.is_file() {
-        return Err(PrependError::NotAFile(format!("{:?}", path)));
+        return Err(not_a_file_error(path, &fs::metadata(path)?.file_type()));
     }
 
     // Permission check (basic write check)
@@ -140,18 +1035,475 @@ pub fn validate_file(path: &Path) -> Result<(), PrependError> {
         return Err(PrependError::NotWritable(format!("{:?}", path)));
     }
 
-    // Extension check
-    if let Some(ext) = path.extension().and_then(OsStr::to_str) {
-        let ext_lower = ext.to_lowercase();
-        if !ALLOWED_EXTENSIONS.contains(&ext_lower.as_str()) {
-            println!(
-                "{}WARNING:{} Uncommon extension '.{}'. Proceeding...",
-                YELLOW, RESET, ext
+    check_extension(path, require_extension)
+}
+
+/// Validates a file using metadata only, without opening it.
+///
+/// Performs the same checks as [`validate_file`], but judges writability from
+/// permission bits instead of opening the file for writing. This avoids
+/// access-time updates and spurious opens, which matters on read-mostly
+/// archival filesystems. On Unix the containing directory must also be
+/// writable, since the temp file is created there.
+///
+/// Permission bits can't express everything (ownership, ACLs, read-only
+/// mounts), so a file that passes here may not be writable by the caller.
+/// The result is renamed into place, so such a file would be replaced
+/// anyway as long as its directory is writable: before modifying a file,
+/// use [`validate_target`], which also checks real access.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file to validate
+/// * `require_extension` - Reject files outside [`ALLOWED_EXTENSIONS`]
+///   instead of warning about them
+///
+/// # Returns
+///
+/// * `Ok(Vec<Warning>)` - File looks valid, with any non-fatal warnings
+/// * `Err(PrependError)` - File validation failed
+pub fn validate_metadata_only(
+    path: &Path,
+    require_extension: bool,
+) -> Result<Vec<Warning>, PrependError> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(PrependError::FileNotFound(format!("{:?}", path)));
+        }
+        Err(e) => return Err(PrependError::Io(e)),
+    };
+    if !metadata.is_file() {
+        return Err(not_a_file_error(path, &metadata.file_type()));
+    }
+    if metadata.permissions().readonly() {
+        return Err(PrependError::NotWritable(format!("{:?}", path)));
+    }
+
+    // Directory permission bits are only meaningful on Unix
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if fs::metadata(dir)?.permissions().readonly() {
+            return Err(PrependError::NotWritable(format!("{:?}", dir)));
+        }
+    }
+
+    check_extension(path, require_extension)
+}
+
+/// Validates the target of `config` the way the CLI does.
+///
+/// Runs [`validate_metadata_only`], then checks that the caller may really
+/// write the file, without opening it on Unix. With `create` a missing
+/// target only needs an existing directory to be created in (and an
+/// allowed extension, as usual).
+pub fn validate_target(config: &Config) -> Result<Vec<Warning>, PrependError> {
+    let path = &config.filename;
+    if config.create && is_missing(path) {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if !dir.is_dir() {
+            return Err(PrependError::FileNotFound(format!("{:?}", dir)));
+        }
+        return check_extension(path, config.require_extension);
+    }
+    let warnings = validate_metadata_only(path, config.require_extension)?;
+    if !can_write(path) {
+        return Err(PrependError::NotWritable(format!("{:?}", path)));
+    }
+    Ok(warnings)
+}
+
+/// Asks the OS whether the caller may write `path`, with the effective user
+/// and groups, so ownership and ACLs count and the file isn't opened.
+#[cfg(unix)]
+pub(crate) fn can_write(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is a NUL-terminated string that outlives the call
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::W_OK, libc::AT_EACCESS) == 0 }
+}
+
+/// Checks that `path` can be opened for writing.
+#[cfg(not(unix))]
+pub(crate) fn can_write(path: &Path) -> bool {
+    OpenOptions::new().write(true).open(path).is_ok()
+}
+
+/// Whether nothing at all is at `path`; a dangling symlink is something,
+/// so `--create` won't write through it.
+fn is_missing(path: &Path) -> bool {
+    matches!(fs::symlink_metadata(path), Err(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Picks the error for a path that isn't a regular file.
+///
+/// FIFOs, sockets and devices get [`PrependError::SpecialFile`] so the user
+/// learns why (say, `/dev/null` or a named pipe) can't work; anything else,
+/// such as a directory, is [`PrependError::NotAFile`].
+fn not_a_file_error(path: &Path, file_type: &fs::FileType) -> PrependError {
+    match special_file_kind(file_type) {
+        Some(kind) => PrependError::SpecialFile(format!("{:?}", path), kind),
+        None => PrependError::NotAFile(format!("{:?}", path)),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn special_file_kind(file_type: &fs::FileType) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        Some("named pipe (FIFO)")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_char_device() {
+        Some("character device")
+    } else if file_type.is_block_device() {
+        Some("block device")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn special_file_kind(_file_type: &fs::FileType) -> Option<&'static str> {
+    None
+}
+
+/// Warns about (or, with `require_extension`, rejects) an uncommon extension.
+fn check_extension(path: &Path, require_extension: bool) -> Result<Vec<Warning>, PrependError> {
+    if has_allowed_extension(path) {
+        return Ok(Vec::new());
+    }
+    if require_extension {
+        return Err(PrependError::DisallowedExtension(format!("{:?}", path)));
+    }
+    Ok(vec![Warning::new(
+        WarningCode::UncommonExtension,
+        format!(
+            "Uncommon extension '.{}'. Proceeding...",
+            path.extension().unwrap_or_default().to_string_lossy()
+        ),
+    )])
+}
+
+/// Returns true if the file has no extension or one from [`ALLOWED_EXTENSIONS`].
+pub fn has_allowed_extension(path: &Path) -> bool {
+    match path.extension().and_then(OsStr::to_str) {
+        Some(ext) => ALLOWED_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => true,
+    }
+}
+
+/// Outcome of a successful [`perform_prepend`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrependStats {
+    /// Size of the original file in bytes
+    pub original_size: u64,
+    /// Size of the written result in bytes
+    pub new_size: u64,
+    /// Line count of the result, if `recount_lines` was set; an
+    /// unterminated last line counts as a line
+    pub lines: Option<u64>,
+    /// Warnings raised along the way (such as an unmatched insertion pattern)
+    pub warnings: Vec<Warning>,
+}
+
+/// Outcome of [`prepend_batch`]
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Each processed target with its result, in processing order
+    pub results: Vec<(PathBuf, Result<PrependStats, PrependError>)>,
+    /// True if the batch stopped early because of `fail_fast` or `transactional`
+    pub aborted: bool,
+    /// Number of targets restored because a `transactional` batch failed
+    pub rolled_back: usize,
+    /// Targets left alone because they were duplicates, weren't modified
+    /// since `modified_since`, weren't tracked under `git_tracked`, or for a
+    /// [`skip_reason`]
+    pub skipped: Vec<PathBuf>,
+}
+
+impl BatchResult {
+    /// Returns the number of targets that failed.
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|(_, r)| r.is_err()).count()
+    }
+}
+
+/// What became of one target of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetOutcome {
+    /// The file was rewritten, or the result written to its output
+    Modified,
+    /// The file was left alone: skipped, or only previewed by a dry run
+    Skipped,
+    /// The target failed
+    Failed,
+}
+
+/// Outcome of [`run_batch`]
+#[derive(Debug, Default)]
+pub struct BatchRun {
+    /// True if any target failed
+    pub failed: bool,
+    /// True if the batch stopped at a failure because of `fail_fast` or
+    /// `transactional`
+    pub aborted: bool,
+    /// Targets never processed because the batch stopped early
+    pub not_processed: Vec<PathBuf>,
+    /// For a failed `transactional` batch, the number of files restored and
+    /// those that could not be
+    pub rollback: Option<(usize, Vec<(PathBuf, PrependError)>)>,
+}
+
+/// Picks the targets of `config` a batch should process, in order.
+///
+/// Targets that resolve to the same file are only kept once (see
+/// [`dedup_targets`]), then those not modified since `modified_since` and,
+/// with `git_tracked`, untracked ones are dropped. Each dropped target is
+/// passed to `on_skip` with the reason: `"duplicate"`, `"not_modified"` or
+/// `"untracked"`.
+///
+/// # Returns
+///
+/// * `Ok(Vec<PathBuf>)` - The targets left to process
+/// * `Err(PrependError)` - git couldn't say which targets are tracked
+pub fn select_targets(
+    config: &Config,
+    mut on_skip: impl FnMut(&Path, &'static str),
+) -> Result<Vec<PathBuf>, PrependError> {
+    let (targets, duplicates) = dedup_targets(&config.targets);
+    for path in &duplicates {
+        on_skip(path, "duplicate");
+    }
+    let (targets, stale) = filter_modified_since(targets, config.modified_since);
+    for path in &stale {
+        on_skip(path, "not_modified");
+    }
+    if !config.git_tracked {
+        return Ok(targets);
+    }
+    let (tracked, untracked) = git::filter_git_tracked(targets)?;
+    for path in &untracked {
+        on_skip(path, "untracked");
+    }
+    Ok(tracked)
+}
+
+/// Runs `process` on each of `targets` (see [`select_targets`]), with the
+/// config for that target.
+///
+/// By default the batch continues past failed targets; with `fail_fast` it
+/// stops at the first failure. With `transactional` (and not `dry_run`),
+/// every target is backed up first (see [`transaction`]); the first failure
+/// stops the batch and the targets `process` modified so far are restored,
+/// so either all targets get the text or none do. Targets it skipped are
+/// never touched by the rollback.
+///
+/// # Returns
+///
+/// * `Ok(BatchRun)` - What happened to the batch as a whole
+/// * `Err(PrependError)` - The backups couldn't be taken; nothing was
+///   processed
+pub fn run_batch(
+    config: &Config,
+    targets: &[PathBuf],
+    mut process: impl FnMut(&Config) -> TargetOutcome,
+) -> Result<BatchRun, PrependError> {
+    let mut transaction = None;
+    if config.transactional && !config.dry_run {
+        transaction = Some(Transaction::begin(targets)?);
+    }
+
+    let mut run = BatchRun::default();
+    for (index, target) in targets.iter().enumerate() {
+        match process(&config.for_target(target)) {
+            TargetOutcome::Modified => {
+                if let Some(transaction) = &mut transaction {
+                    transaction.record(target);
+                }
+            }
+            TargetOutcome::Skipped => {}
+            TargetOutcome::Failed => {
+                run.failed = true;
+                if config.fail_fast || transaction.is_some() {
+                    run.aborted = true;
+                    run.not_processed = targets[index + 1..].to_vec();
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some(transaction) = transaction {
+        if run.failed {
+            run.rollback = Some(transaction.rollback());
+        } else {
+            transaction.commit();
+        }
+    }
+    Ok(run)
+}
+
+/// Validates and prepends to every target in `config.targets`.
+///
+/// Targets are picked with [`select_targets`] and processed with
+/// [`run_batch`], as the command-line tool does. A target skipped because
+/// it is locked under [`LockMode::Skip`] is reported as an error but never
+/// aborts the batch. If the backups of a `transactional` batch can't be
+/// taken, or git can't say which targets are tracked, nothing is processed
+/// and the error is reported for the first target.
+///
+/// Only the prepend itself is performed; `dry_run` and `emit_patch` are
+/// handled by the command-line tool.
+pub fn prepend_batch(config: &Config) -> BatchResult {
+    let mut skipped = Vec::new();
+    let mut results = Vec::new();
+    let selected = select_targets(config, |path, _| skipped.push(path.to_path_buf()));
+    let run = selected.and_then(|targets| {
+        run_batch(config, &targets, |target_config| {
+            if skip_reason(target_config).is_some() {
+                skipped.push(target_config.filename.clone());
+                return TargetOutcome::Skipped;
+            }
+            let result = validate_target(target_config).and_then(|warnings| {
+                let warnings = config.strict.enforce(warnings)?;
+                let mut stats = perform_prepend(target_config)?;
+                stats.warnings.splice(0..0, warnings);
+                Ok(stats)
+            });
+            let outcome = match &result {
+                Ok(_) => TargetOutcome::Modified,
+                Err(PrependError::Locked(_))
+                    if config.lock == LockMode::Skip && config.strict != Strictness::Fail =>
+                {
+                    TargetOutcome::Skipped
+                }
+                Err(_) => TargetOutcome::Failed,
+            };
+            results.push((target_config.filename.clone(), result));
+            outcome
+        })
+    });
+
+    let run = match run {
+        Ok(run) => run,
+        Err(e) => {
+            results.extend(
+                config
+                    .targets
+                    .first()
+                    .map(|target| (target.clone(), Err(e))),
             );
+            return BatchResult {
+                results,
+                aborted: true,
+                rolled_back: 0,
+                skipped,
+            };
         }
+    };
+    let mut rolled_back = 0;
+    if let Some((restored, failures)) = run.rollback {
+        rolled_back = restored;
+        results.extend(failures.into_iter().map(|(path, e)| (path, Err(e))));
     }
+    BatchResult {
+        results,
+        aborted: run.aborted,
+        rolled_back,
+        skipped,
+    }
+}
 
-    Ok(())
+/// Compares two byte streams chunk by chunk.
+///
+/// At most [`BUFFER_SIZE`] bytes of each are held at a time, and reading
+/// stops at the first chunk where they differ, so a mismatch near the start
+/// of two huge files is found without reading the rest.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether both streams have the same length and content
+/// * `Err(io::Error)` - Reading either stream failed
+pub fn streams_equal<A: Read, B: Read>(mut a: A, mut b: B) -> io::Result<bool> {
+    let mut chunk_a = vec![0; BUFFER_SIZE];
+    let mut chunk_b = vec![0; BUFFER_SIZE];
+    loop {
+        let len_a = read_chunk(&mut a, &mut chunk_a)?;
+        let len_b = read_chunk(&mut b, &mut chunk_b)?;
+        if chunk_a[..len_a] != chunk_b[..len_b] {
+            return Ok(false);
+        }
+        if len_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills `buf` as far as the reader allows, so two streams yielding short
+/// reads at different points still line up chunk for chunk. Returns the
+/// number of bytes read, less than `buf.len()` only at end of stream.
+fn read_chunk(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Returns why the target of `config` should be left alone, if it should:
+/// `"empty"` for a zero-byte file under `skip_empty`, `"present"` if it
+/// already starts with the text under `skip_if_present`, or `"unchanged"`
+/// if its managed block already has the text's hash under `hash_marker`.
+///
+/// Only the file's length is looked at for `skip_empty`, before anything is
+/// opened. Errors count as "don't skip", so they are reported by the prepend
+/// itself.
+pub fn skip_reason(config: &Config) -> Option<&'static str> {
+    if config.skip_empty && fs::metadata(&config.filename).is_ok_and(|m| m.len() == 0) {
+        return Some("empty");
+    }
+    if config.skip_if_present && matches!(present::needs_prepend(config), Ok(false)) {
+        return Some("present");
+    }
+    if matches!(managed::is_current(config), Ok(true)) {
+        return Some("unchanged");
+    }
+    None
+}
+
+/// Splits `targets` into those modified after `cutoff` and those that
+/// weren't.
+///
+/// Targets whose modification time can't be read are kept, so that
+/// validation reports the actual problem. Without a cutoff everything is
+/// kept.
+pub fn filter_modified_since(
+    targets: Vec<PathBuf>,
+    cutoff: Option<SystemTime>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    match cutoff {
+        Some(cutoff) => targets
+            .into_iter()
+            .partition(|target| since::modified_since(target, cutoff).unwrap_or(true)),
+        None => (targets, Vec::new()),
+    }
 }
 
 /// Performs the prepend operation on a file.
@@ -162,47 +1514,190 @@ pub fn validate_file(path: &Path) -> Result<(), PrependError> {
 /// 3. Streams the original file content to the temporary file
 /// 4. Atomically replaces the original file with the temporary file
 ///
+/// With `fast_temp`, steps 2 and 3 write to the system temp dir instead and
+/// the result is moved to the temporary file before step 4.
+///
+/// With `create`, a missing target is first created empty. With `mode`,
+/// the temporary file gets those permission bits before step 4 if the
+/// destination is new (or with `force`).
+///
+/// With a pattern [`InsertPosition`], steps 2 and 3 are interleaved: the
+/// text is inserted where the pattern matches (see [`insert`]).
+///
 /// # Arguments
 ///
 /// * `config` - Configuration containing the file path and text to prepend
 ///
 /// # Returns
 ///
-/// * `Ok(())` - Prepend operation completed successfully
-/// * `Err(PrependError)` - I/O error occurred during the operation
+/// * `Ok(PrependStats)` - Prepend operation completed; carries the sizes
+///   and any warnings
+/// * `Err(PrependError)` - I/O error occurred during the operation, the
+///   text lacks a trailing newline while `strict_newline` is set, the
+///   target is locked by another process (see [`lock`]), no line
+///   matched the insertion pattern under [`NoMatch::Error`], or the temp
+///   file would not fit while `check_space` is set (see [`space`])
 ///
 /// # Safety
 ///
 /// This function uses atomic file operations to minimize the risk of data loss.
-/// If the operation fails, the temporary file is cleaned up automatically.
-pub fn perform_prepend(config: &Config) -> Result<(), PrependError> {
+/// If the operation fails, the temporary file is cleaned up automatically,
+/// except in resumable mode where it is kept so a later run can continue
+/// (see [`resume`]).
+pub fn perform_prepend(config: &Config) -> Result<PrependStats, PrependError> {
+    let requested_eol = config.normalize_eol;
+    let config = &*resolve_text(config)?;
     let source_path = &config.filename;
 
-    // Create a temp file in the SAME DIRECTORY as the source.
-    // This is crucial for atomic moves across filesystems.
-    let mut temp_path = source_path.clone();
-    temp_path.set_extension("tmp_prepend");
+    // Refuse before touching the disk so the header can't merge into line 1
+    if config.strict_newline && !config.prepend_text.ends_with('\n') {
+        return Err(PrependError::MissingTrailingNewline);
+    }
+    // The resumable copy moves raw blocks, never lines
+    if config.resumable && !config.transforms.is_empty() {
+        return Err(PrependError::ConflictingOptions(
+            "--resumable".to_string(),
+            "custom content transforms".to_string(),
+        ));
+    }
+    if config.resumable && config.normalize_eol.is_some() {
+        return Err(PrependError::ConflictingOptions(
+            "--resumable".to_string(),
+            "--normalize-eol".to_string(),
+        ));
+    }
+    if config.resumable && config.hash_marker.is_some() {
+        return Err(PrependError::ConflictingOptions(
+            "--resumable".to_string(),
+            "--hash-marker".to_string(),
+        ));
+    }
 
-    let source_file = File::open(source_path)?;
-    let temp_file = File::create(&temp_path)?;
+    let destination = config.destination();
+    let temp_path = config.temp_path();
+
+    let created = config.create && is_missing(source_path);
+    if created {
+        File::create_new(source_path)?;
+    }
+    // A failed run must not leave the new, empty target behind
+    let created_target = RemoveOnDrop(created.then_some(source_path));
+    let new_file = created || !destination.exists();
+
+    if config.check_space {
+        let temp_dir = match temp_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if let Some(available) = space::available_space(temp_dir)? {
+            space::check_space(source_path, projected_size(config)?, available)?;
+        }
+    }
+
+    let mut warnings = config.strict.enforce(text_warnings(&config.prepend_text))?;
+    // resolve_text drops the normalization for targets that look binary
+    if requested_eol.is_some() && config.normalize_eol.is_none() {
+        let warning = Warning::new(
+            WarningCode::EolNotNormalized,
+            format!(
+                "{:?} does not look like UTF-8 text; line endings left as they are. Use --force to normalize anyway.",
+                source_path
+            ),
+        );
+        warnings.extend(config.strict.enforce(vec![warning])?);
+    }
+    // Under --strict an unmatched pattern must not leave the text appended
+    let no_match = match config.strict {
+        Strictness::Fail => NoMatch::Error,
+        _ => config.no_match,
+    };
+
+    // Held until the function returns, i.e. through the rename
+    let source_file = lock::open_source(source_path, config.lock)?;
+    let original_size = source_file.metadata()?.len();
+    if config.resumable {
+        resume::perform_resumable(config, source_file, &temp_path)?;
+        created_target.disarm();
+        return Ok(PrependStats {
+            original_size,
+            new_size: fs::metadata(destination)?.len(),
+            lines: None,
+            warnings,
+        });
+    }
+
+    // With --fast-temp the copy is written elsewhere and staged at temp_path
+    // once it is complete
+    let (write_path, temp_file) = match fast_temp_file(config, &temp_path, &mut warnings)? {
+        Some(scratch) => scratch,
+        None => (temp_path.clone(), File::create(&temp_path)?),
+    };
+    // The scratch file lives outside the target's directory, where nothing
+    // else would ever clean it up, so every early return removes it
+    let scratch = RemoveOnDrop((write_path != temp_path).then_some(write_path.as_path()));
 
     // Use Buffering for speed
     let mut reader = BufReader::with_capacity(BUFFER_SIZE, source_file);
+    // Lines are counted on the way out, so no second pass is needed
+    let temp_file = LineCounter::new(temp_file, config.recount_lines);
     let mut writer = BufWriter::with_capacity(BUFFER_SIZE, temp_file);
 
-    // 1. Write the new header
-    writer.write_all(config.prepend_text.as_bytes())?;
-
-    // 2. Stream the original file content
-    io::copy(&mut reader, &mut writer)?;
+    let header = config.prepend_text.as_bytes();
+    // 1. and 2. Write the new header and stream the original file content
+    if !copy_with_header(config, &mut reader, &mut writer, header, no_match)? {
+        let pattern = config.position.pattern().map(ToString::to_string);
+        let pattern = pattern.unwrap_or_default();
+        match no_match {
+            NoMatch::Append => warnings.push(Warning::new(
+                WarningCode::PatternNotMatched,
+                format!("No line matches '{}'. Text appended at end.", pattern),
+            )),
+            NoMatch::Error => {
+                drop(writer);
+                let _ = fs::remove_file(&write_path);
+                return Err(PrependError::PatternNotFound(
+                    format!("{:?}", source_path),
+                    pattern,
+                ));
+            }
+        }
+    }
 
     // 3. Flush to ensure all data is on disk
     writer.flush()?;
+    if config.newline == NewlinePolicy::Force && writer.get_ref().last != Some(b'\n') {
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    let new_size = writer.get_ref().inner.metadata()?.len();
+    let lines = writer.get_ref().lines();
+    drop(writer);
+
+    if write_path != temp_path {
+        stage_fast_temp(&write_path, &temp_path)?;
+    }
+    scratch.disarm();
+    if let Some(mode) = config.mode.filter(|_| new_file || config.force)
+        && let Err(e) = set_mode(&temp_path, mode)
+    {
+        let _ = fs::remove_file(&temp_path);
+        return Err(PrependError::Io(e));
+    }
+
+    simulate_crash_before_rename();
 
     // 4. Atomic Replace
     // fs::rename is atomic on POSIX systems if on the same mount point
-    match fs::rename(&temp_path, source_path) {
-        Ok(_) => Ok(()),
+    match fs::rename(&temp_path, destination) {
+        Ok(_) => {
+            created_target.disarm();
+            Ok(PrependStats {
+                original_size,
+                new_size,
+                lines,
+                warnings,
+            })
+        }
         Err(e) => {
             // Cleanup temp file if rename fails
             let _ = fs::remove_file(&temp_path);
@@ -211,6 +1706,654 @@ pub fn perform_prepend(config: &Config) -> Result<(), PrependError> {
     }
 }
 
+/// Creates the `--fast-temp` scratch file in the system temp dir.
+///
+/// Returns `None` without `fast_temp`, or with a [`WarningCode::FastTempUnavailable`]
+/// warning added if the temp dir can't be written, in which case the copy
+/// falls back to `temp_path` as usual.
+fn fast_temp_file(
+    config: &Config,
+    temp_path: &Path,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<(PathBuf, File)>, PrependError> {
+    if !config.fast_temp {
+        return Ok(None);
+    }
+    let name = temp_path.file_name().unwrap_or_default().to_string_lossy();
+    let scratch = env::temp_dir().join(format!("{}.{}", process::id(), name));
+    match File::create(&scratch) {
+        Ok(file) => Ok(Some((scratch, file))),
+        Err(e) => {
+            let warning = Warning::new(
+                WarningCode::FastTempUnavailable,
+                format!(
+                    "Could not use the system temp dir for --fast-temp ({}). Writing next to the target instead.",
+                    e
+                ),
+            );
+            warnings.extend(config.strict.enforce(vec![warning])?);
+            Ok(None)
+        }
+    }
+}
+
+/// Removes the file at its path, if any, when dropped.
+struct RemoveOnDrop<'a>(Option<&'a Path>);
+
+impl RemoveOnDrop<'_> {
+    /// Keeps the file.
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for RemoveOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Gives `path` the permission bits `mode`.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Permission bits don't exist elsewhere, so `--mode` does nothing.
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Moves the finished `--fast-temp` copy to `temp_path`, next to the
+/// destination, so the final rename stays atomic.
+///
+/// A plain rename suffices when the system temp dir shares the destination's
+/// filesystem; otherwise the copy is streamed over. The scratch file is
+/// removed either way.
+fn stage_fast_temp(scratch: &Path, temp_path: &Path) -> Result<(), PrependError> {
+    if fs::rename(scratch, temp_path).is_ok() {
+        return Ok(());
+    }
+    let copied = fs::copy(scratch, temp_path);
+    let _ = fs::remove_file(scratch);
+    if let Err(e) = copied {
+        let _ = fs::remove_file(temp_path);
+        return Err(PrependError::Io(e));
+    }
+    Ok(())
+}
+
+/// Prepends `prepend` to a file the caller already has open.
+///
+/// For embedders that manage file handles themselves. `source` must be open
+/// for reading and `path` must name the same file; the temp file is created
+/// next to it and renamed over it exactly as in [`perform_prepend`]. The
+/// whole file is copied regardless of the handle's current position.
+///
+/// The text is written as given: none of the `Config` options (transforms,
+/// locking, insertion position) apply here.
+///
+/// After a successful call `source` still refers to the original content,
+/// which the rename has unlinked; reopen `path` to read the result.
+///
+/// # Returns
+///
+/// * `Ok(PrependStats)` - The file was replaced; `lines` is always `None`
+/// * `Err(PrependError)` - Reading `source` or writing the temp file failed;
+///   the temp file is removed and the original is left untouched
+pub fn prepend_into_file(
+    source: &mut File,
+    path: &Path,
+    prepend: &[u8],
+) -> Result<PrependStats, PrependError> {
+    let original_size = source.metadata()?.len();
+    let temp_path = temp_path_for(path);
+    let temp_file = File::create(&temp_path)?;
+
+    let copied = (|| -> io::Result<u64> {
+        let mut writer = BufWriter::with_capacity(BUFFER_SIZE, temp_file);
+        writer.write_all(prepend)?;
+        source.seek(SeekFrom::Start(0))?;
+        io::copy(
+            &mut BufReader::with_capacity(BUFFER_SIZE, &mut *source),
+            &mut writer,
+        )?;
+        writer.flush()?;
+        writer.get_ref().metadata().map(|metadata| metadata.len())
+    })();
+    let new_size = match copied.and_then(|size| fs::rename(&temp_path, path).map(|_| size)) {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(PrependError::Io(e));
+        }
+    };
+
+    Ok(PrependStats {
+        original_size,
+        new_size,
+        lines: None,
+        warnings: text_warnings(&String::from_utf8_lossy(prepend)),
+    })
+}
+
+/// Test hook: aborts the process right before the final rename when
+/// `PREPEND_TEST_CRASH_BEFORE_RENAME` is set, simulating a crash with the
+/// temp file fully written. Only compiled into debug builds.
+#[cfg(debug_assertions)]
+fn simulate_crash_before_rename() {
+    if env::var_os("PREPEND_TEST_CRASH_BEFORE_RENAME").is_some() {
+        process::abort();
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn simulate_crash_before_rename() {}
+
+/// Computes the size the destination will have after prepending, without
+/// writing anything.
+///
+/// This is the size of the original plus the text as rendered by
+/// [`render_header`], so transforms and the added newline are accounted
+/// for, plus the final newline [`NewlinePolicy::Force`] may add. In the
+/// default top-of-file mode it only costs a metadata call (plus a peek at the
+/// first line for `--indent-match`, and at the last byte under `Force`); with
+/// `limit_lines` or a pattern [`InsertPosition`] the original has to be
+/// scanned once.
+///
+/// # Returns
+///
+/// * `Ok(u64)` - Projected size in bytes
+/// * `Err(PrependError)` - The original could not be read, or no line matched
+///   the insertion pattern under [`NoMatch::Error`]
+pub fn projected_size(config: &Config) -> Result<u64, PrependError> {
+    let header = render_header(config)?;
+    let original_size = fs::metadata(&config.filename)?.len();
+
+    if matches!(config.position, InsertPosition::Top)
+        && config.limit_lines.is_none()
+        && config.transforms.is_empty()
+        && normalized_eol(config).is_none()
+        && config.hash_marker.is_none()
+    {
+        let size = original_size + header.len() as u64;
+        if config.newline != NewlinePolicy::Force {
+            return Ok(size);
+        }
+        let last = if original_size > 0 {
+            let mut file = File::open(&config.filename)?;
+            file.seek(SeekFrom::End(-1))?;
+            let mut byte = [0];
+            file.read_exact(&mut byte)?;
+            Some(byte[0])
+        } else {
+            header.bytes().last()
+        };
+        return Ok(size + u64::from(last != Some(b'\n')));
+    }
+
+    // Run the same copy as perform_prepend, but only count the bytes
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(&config.filename)?);
+    let mut counter = ByteCounter::default();
+    let matched = copy_with_header(
+        config,
+        &mut reader,
+        &mut counter,
+        header.as_bytes(),
+        config.no_match,
+    )?;
+    if let (false, NoMatch::Error) = (matched, config.no_match) {
+        return Err(PrependError::PatternNotFound(
+            format!("{:?}", config.filename),
+            config
+                .position
+                .pattern()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ));
+    }
+    let forced = config.newline == NewlinePolicy::Force && counter.last != Some(b'\n');
+    Ok(counter.len + u64::from(forced))
+}
+
+/// Reports whether prepending per `config` would change its destination,
+/// e.g. for a dry run's exit status.
+///
+/// The projected result is streamed against the destination's current
+/// content with [`streams_equal`], so nothing is written or held in memory.
+/// A target [`skip_reason`] leaves alone never changes; a destination that
+/// doesn't exist yet always does.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether the result would differ from the current content
+/// * `Err(PrependError)` - A file could not be read, or no line matched the
+///   insertion pattern under [`NoMatch::Error`]
+pub fn would_change(config: &Config) -> Result<bool, PrependError> {
+    if skip_reason(config).is_some() {
+        return Ok(false);
+    }
+    let config = &*resolve_text(config)?;
+    let current = match File::open(config.destination()) {
+        Ok(current) => current,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
+    // Usually the size alone settles it
+    if projected_size(config)? != current.metadata()?.len() {
+        return Ok(true);
+    }
+
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(&config.filename)?);
+    let mut projected = MatchWriter::new(BufReader::with_capacity(BUFFER_SIZE, current));
+    let header = config.prepend_text.as_bytes();
+    if !copy_with_header(config, &mut reader, &mut projected, header, config.no_match)?
+        && config.no_match == NoMatch::Error
+    {
+        return Err(PrependError::PatternNotFound(
+            format!("{:?}", config.filename),
+            config
+                .position
+                .pattern()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ));
+    }
+    if config.newline == NewlinePolicy::Force && projected.last != Some(b'\n') {
+        projected.write_all(b"\n")?;
+    }
+    Ok(!projected.finish()?)
+}
+
+/// Writes `header` and the original from `reader` to `writer`, with the
+/// header placed according to `config.position` and the original capped at
+/// `config.limit_lines`. The line steps of `config.transforms` are applied
+/// to the original on the way, and its line endings are rewritten per
+/// `config.normalize_eol`. With `config.hash_marker`, a managed block at
+/// the top of the original is replaced rather than kept.
+///
+/// Returns `false` if the position is a pattern that matched no line.
+fn copy_with_header(
+    config: &Config,
+    reader: &mut BufReader<File>,
+    writer: &mut impl Write,
+    header: &[u8],
+    no_match: NoMatch,
+) -> io::Result<bool> {
+    if let (Some(comment), InsertPosition::Top) = (&config.hash_marker, &config.position)
+        && let Some(block) = managed::find_block(&config.filename, comment)?
+    {
+        reader.seek(SeekFrom::Start(block.len))?;
+    }
+    let ending = normalized_eol(config).map(|style| style.as_str().as_bytes());
+    if config.transforms.is_empty() && ending.is_none() {
+        return copy_at_position(config, reader, writer, header, no_match);
+    }
+    let mut lines = TransformedLines::new(reader, &config.transforms, ending);
+    copy_at_position(config, &mut lines, writer, header, no_match)
+}
+
+/// Writes `header` at `config.position` while copying `reader`.
+fn copy_at_position(
+    config: &Config,
+    reader: &mut (impl BufRead + Seek),
+    writer: &mut impl Write,
+    header: &[u8],
+    no_match: NoMatch,
+) -> io::Result<bool> {
+    match &config.position {
+        InsertPosition::Top => {
+            writer.write_all(header)?;
+            match config.limit_lines {
+                Some(limit) => copy_lines(reader, writer, limit)?,
+                None => {
+                    io::copy(reader, writer)?;
+                }
+            }
+            Ok(true)
+        }
+        InsertPosition::BeforePattern(pattern) | InsertPosition::AfterPattern(pattern) => {
+            let after = matches!(config.position, InsertPosition::AfterPattern(_));
+            insert::copy_inserting_at(reader, writer, header, pattern, after, no_match)
+        }
+        InsertPosition::UnderSection(section) => {
+            // A missing section is created, so there is no no-match case
+            insert::copy_under_section(reader, writer, header, section)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Returns a dry-run view of where the text would land: a few lines of
+/// context on either side of the inserted block.
+///
+/// The original is streamed through the same copy as [`perform_prepend`],
+/// so only the context lines and the text are held in memory.
+///
+/// # Returns
+///
+/// * `Ok(InsertionPreview)` - The lines around the insertion point
+/// * `Err(PrependError)` - The original could not be read, or no line matched
+///   the insertion pattern under [`NoMatch::Error`]
+pub fn preview_insertion(
+    config: &Config,
+    context: usize,
+) -> Result<InsertionPreview, PrependError> {
+    let header = render_header(config)?;
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(&config.filename)?);
+    let mut preview = PreviewWriter::new(context);
+    let marked = preview::mark(header.as_bytes());
+    let matched = copy_with_header(config, &mut reader, &mut preview, &marked, config.no_match)?;
+    if let (false, NoMatch::Error) = (matched, config.no_match) {
+        return Err(PrependError::PatternNotFound(
+            format!("{:?}", config.filename),
+            config
+                .position
+                .pattern()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ));
+    }
+    Ok(preview.finish())
+}
+
+/// A writer that discards its input and checks it against `expected`.
+struct MatchWriter<R> {
+    expected: R,
+    matches: bool,
+    last: Option<u8>,
+}
+
+impl<R: Read> MatchWriter<R> {
+    fn new(expected: R) -> Self {
+        MatchWriter {
+            expected,
+            matches: true,
+            last: None,
+        }
+    }
+
+    /// Returns whether everything written matched all of `expected`.
+    fn finish(mut self) -> io::Result<bool> {
+        Ok(self.matches && streams_equal(&mut self.expected, io::empty())?)
+    }
+}
+
+impl<R: Read> Write for MatchWriter<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // After the first difference the rest is not worth reading
+        if self.matches {
+            let expected = (&mut self.expected).take(buf.len() as u64);
+            self.matches = streams_equal(expected, buf)?;
+        }
+        if let Some(&last) = buf.last() {
+            self.last = Some(last);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that discards its input, counting the bytes and keeping the last.
+#[derive(Default)]
+struct ByteCounter {
+    len: u64,
+    last: Option<u8>,
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len() as u64;
+        if let Some(&last) = buf.last() {
+            self.last = Some(last);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that counts the lines passing through it, if enabled.
+struct LineCounter<W> {
+    inner: W,
+    enabled: bool,
+    newlines: u64,
+    last: Option<u8>,
+}
+
+impl<W> LineCounter<W> {
+    fn new(inner: W, enabled: bool) -> Self {
+        LineCounter {
+            inner,
+            enabled,
+            newlines: 0,
+            last: None,
+        }
+    }
+
+    /// Returns the number of lines written, counting an unterminated last
+    /// line, or `None` if counting is disabled.
+    fn lines(&self) -> Option<u64> {
+        self.enabled
+            .then(|| self.newlines + u64::from(self.last.is_some_and(|b| b != b'\n')))
+    }
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if written > 0 {
+            let buf = &buf[..written];
+            if self.enabled {
+                self.newlines += buf.iter().filter(|&&b| b == b'\n').count() as u64;
+            }
+            // Also tracked without counting, for NewlinePolicy::Force
+            self.last = buf.last().copied();
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Checks the text itself for likely mistakes.
+///
+/// Currently flags NUL bytes, which make tools that treat the file as C
+/// strings stop reading early; the warning gives the first offset. These are
+/// never fatal.
+pub fn text_warnings(text: &str) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if let Some(offset) = text.bytes().position(|b| b == 0) {
+        warnings.push(Warning::new(
+            WarningCode::NulInText,
+            format!("Prepend text contains a NUL byte at offset {}.", offset),
+        ));
+    }
+    warnings
+}
+
+/// Renders the text that will actually be written to the target.
+///
+/// Applies the configured transforms (template placeholders, then the
+/// `--replace-regex` rewrites, then canonicalization, then indentation, then
+/// tab conversion, then the [`NewlinePolicy`]) to `prepend_text`. Some
+/// transforms depend on the target's path or content, so the result can
+/// differ per file.
+///
+/// # Arguments
+///
+/// * `config` - Configuration containing the file path and text to prepend
+///
+/// # Returns
+///
+/// * `Ok(String)` - The final text
+/// * `Err(PrependError)` - The target could not be read for a content-dependent transform
+pub fn render_header(config: &Config) -> Result<String, PrependError> {
+    let mut text = config.prepend_text.clone();
+
+    if let Some(values) = &config.template {
+        text = values.expand(&text);
+    }
+
+    for replacement in &config.replacements {
+        text = replacement.apply(&text, &config.filename);
+    }
+
+    if config.canonicalize {
+        text = transform::canonicalize(&text);
+    }
+
+    if let Some(indent) = config.indent {
+        let prefix = match indent {
+            Indent::Fixed(width) => transform::indent_prefix(width, config.indent_style),
+            Indent::MatchFirstLine => {
+                let head = detect::sniff(&config.filename)?;
+                let first_line = head.split(|&b| b == b'\n').next().unwrap_or_default();
+                transform::leading_whitespace(&String::from_utf8_lossy(first_line)).to_string()
+            }
+        };
+        text = transform::indent_lines(&text, &prefix);
+    }
+
+    let context = FileContext::new(&config.filename);
+    if let Some(tabs) = &config.tabs {
+        text = transform_header(tabs, &text, &context);
+    }
+    for custom in &config.transforms {
+        text = transform_header(custom.as_ref(), &text, &context);
+    }
+
+    let text = config.newline.apply_to_text(&text);
+    let text = match normalized_eol(config) {
+        Some(style) => transform::normalize_line_endings(&text, style),
+        None => text,
+    };
+    Ok(match &config.hash_marker {
+        Some(comment) => managed::mark(&text, comment),
+        None => text,
+    })
+}
+
+/// The line ending `config.normalize_eol` asks for, unless the target
+/// doesn't look like UTF-8 text and `force` isn't set.
+fn normalized_eol(config: &Config) -> Option<EolStyle> {
+    let style = config.normalize_eol?;
+    if config.force {
+        return Some(style);
+    }
+    let prefix = detect::sniff(&config.filename).unwrap_or_default();
+    matches!(
+        detect::detect_encoding(&prefix),
+        detect::Encoding::Utf8 | detect::Encoding::Utf8Bom
+    )
+    .then_some(style)
+}
+
+/// Runs the header step of `transform` over `text`.
+fn transform_header(transform: &dyn ContentTransform, text: &str, context: &FileContext) -> String {
+    let transformed = transform.transform_header(text.as_bytes(), context);
+    String::from_utf8_lossy(&transformed).into_owned()
+}
+
+/// Returns `config` with its text rendered for this target.
+fn resolve_text(config: &Config) -> Result<Cow<'_, Config>, PrependError> {
+    if config.indent.is_none()
+        && !config.canonicalize
+        && config.template.is_none()
+        && config.replacements.is_empty()
+        && config.tabs.is_none()
+        && config.transforms.is_empty()
+        && config.newline == NewlinePolicy::Preserve
+        && config.normalize_eol.is_none()
+        && config.hash_marker.is_none()
+    {
+        return Ok(Cow::Borrowed(config));
+    }
+    Ok(Cow::Owned(Config {
+        prepend_text: render_header(config)?,
+        indent: None,
+        canonicalize: false,
+        template: None,
+        replacements: Vec::new(),
+        tabs: None,
+        normalize_eol: normalized_eol(config),
+        // The text is rendered now; only the line steps are still to come
+        transforms: config
+            .transforms
+            .iter()
+            .map(|custom| Arc::new(LinesOnly(custom.clone())) as Arc<dyn ContentTransform>)
+            .collect(),
+        ..config.clone()
+    }))
+}
+
+/// Reports whether replacing the destination of `config` would be atomic.
+///
+/// The temp file is created next to the destination, so the final rename is
+/// atomic as long as both are on the same filesystem. That only breaks when
+/// the destination itself is a mount point (e.g. a bind-mounted file), in
+/// which case the rename would cross filesystems.
+///
+/// Returns true when the device can't be determined (non-Unix platforms, or
+/// a destination that doesn't exist yet).
+pub fn rename_is_atomic(config: &Config) -> bool {
+    let destination = config.destination();
+    let temp_path = config.temp_path();
+    let temp_dir = match temp_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    same_device(destination, temp_dir).unwrap_or(true)
+}
+
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(fs::metadata(a).ok()?.dev() == fs::metadata(b).ok()?.dev())
+}
+
+#[cfg(not(unix))]
+fn same_device(_a: &Path, _b: &Path) -> Option<bool> {
+    None
+}
+
+/// Copies at most `limit` lines from `reader` to `writer`.
+///
+/// An unterminated last line counts as a line.
+fn copy_lines(reader: &mut impl BufRead, writer: &mut impl Write, limit: usize) -> io::Result<()> {
+    let mut line = Vec::new();
+    for _ in 0..limit {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Returns the temp file path used while prepending to `source`.
+///
+/// The temp file lives in the SAME DIRECTORY as the source.
+/// This is crucial for atomic moves across filesystems.
+///
+/// This is the default naming; see [`Config::temp_name_fn`] to override it.
+pub fn temp_path_for(source: &Path) -> PathBuf {
+    source.with_extension("tmp_prepend")
+}
+
 /// Prints help information for the command-line tool.
 ///
 /// # Arguments
@@ -218,11 +2361,92 @@ pub fn perform_prepend(config: &Config) -> Result<(), PrependError> {
 /// * `prog_name` - Name of the program executable
 pub fn print_help(prog_name: &str) {
     println!(
-        "{}Usage:{} {} [OPTIONS] <filename> [text]",
+        "{}Usage:{} {} [OPTIONS] <filename>... [text]",
         BLUE, RESET, prog_name
     );
     println!("\nSafely prepends text to the beginning of a file using buffering.");
     println!("\n{}Options:{}", BLUE, RESET);
-    println!("  --dry-run   Show what would happen without modifying the file.");
-    println!("  --help      Show this message.");
+    println!("  --dry-run               Show what would happen without modifying the file.");
+    println!("                          Exits 1 if any file would change, 0 if none would.");
+    println!("  --only-added            Dry run that prints just the lines to be inserted.");
+    println!("  --strict-newline        Fail if the text does not end with a newline.");
+    println!("  --newline <policy>      Final newlines: auto (default), preserve, force, none.");
+    println!("  --no-newline            Same as --newline none.");
+    println!("  --normalize-eol <style> Rewrite every line ending of the file to lf or crlf.");
+    println!(
+        "  --force                 With --normalize-eol, also rewrite files that look binary."
+    );
+    println!("                          With --mode, also set the mode of existing files.");
+    println!("  --no-glob               Treat '*', '?' and '[' in paths literally.");
+    println!(
+        "  --emit-patch <path>     Write the change as a unified diff instead of applying it."
+    );
+    println!("  --resumable             Checkpoint progress so an interrupted run can resume.");
+    println!("  -o, --output <path>     Write the result to <path> instead of the target.");
+    println!("  --create                Create missing targets, starting them out empty.");
+    println!("  --mode <octal>          Permission bits for created files, e.g. 755 (Unix).");
+    println!("  --limit-lines <N>       Keep only the first N lines of the original.");
+    println!("  --allow-truncate        Allow --limit-lines to truncate the target in place.");
+    println!("  --format <text|json>    Report results as text (default) or JSON lines.");
+    println!("  --lock                  Lock the target while rewriting it; fail if busy.");
+    println!("  --lock-wait             Like --lock, but wait for the lock.");
+    println!("  --skip-locked           Like --lock, but skip targets that are busy.");
+    println!("  --indent <N>            Indent each line of the text by N spaces.");
+    println!("  --indent-tabs           Use tabs instead of spaces for --indent.");
+    println!("  --indent-match          Indent the text like the file's first line.");
+    println!("  --canonicalize-header   Normalize whitespace and newlines in the text.");
+    println!("  --expand-tabs <N>       Convert tabs in the text to spaces (tab stops every N).");
+    println!(
+        "  --unexpand              Convert the text's leading spaces to tabs (stops every 8)."
+    );
+    println!("  --template              Expand {{date}}, {{year}} and {{timestamp}} in the text.");
+    println!("  --replace-regex <re>=<replacement>");
+    println!("                          Rewrite matches in the text per file ($1, {{stem}}, ...).");
+    println!("  --before-pattern <re>   Insert before the first line matching <re>.");
+    println!("  --after-pattern <re>    Insert after the first line matching <re>.");
+    println!("  --under-section <name>  Insert under the [name] header, creating it if absent.");
+    println!("  --time                  Report elapsed time and throughput per file.");
+    println!("  --skip-empty            Skip zero-byte files instead of adding the text.");
+    println!("  --skip-if-present       Skip files that already start with the text.");
+    println!("  --present-match <mode>  How to compare: exact, trimmed, ignore-first-line.");
+    println!("  --hash-marker <comment> End the text with '<comment> prepend-hash: <hash>';");
+    println!("                          skip files whose hash matches, replace the old block.");
+    println!("  --print-temp-path       Report where each target's temp file is created.");
+    println!("  --write-manifest <path> Record every file, its sizes and its outcome in <path>.");
+    println!("  --manifest-format <json|csv>");
+    println!("                          Format of the --write-manifest file (default json).");
+    println!("  --status-fd <fd>        Report progress as JSON lines on file descriptor <fd>.");
+    println!("  --text-stdin            Read the text from stdin; all positionals are targets.");
+    println!("  --text-command <cmd>    Use the output of <cmd> (run by the shell) as the text.");
+    println!("  --require-extension     Refuse files with an uncommon extension.");
+    println!("  --check-space           Refuse files whose temp copy would not fit on disk.");
+    println!("  --fast-temp             Write the copy in the system temp dir, then move it over.");
+    println!("  --recount-lines         Report the line count of the result.");
+    println!("  --spec <spec>           Target and text as 'file=<path>;text=<text>'; repeatable.");
+    println!("                          Escape ';', '=' and '\\' in values with a backslash.");
+    println!(
+        "  --modified-since <t>    Only process files modified after <t> (7d, 2h, or RFC 3339)."
+    );
+    println!("  --git-tracked           Only process files tracked by git (e.g. with '**' globs).");
+    println!("  --pairs                 Read 'path<TAB>text' lines from stdin; one text per file.");
+    println!("  --pairs-delimiter <c>   Separate --pairs fields with <c> instead of a tab.");
+    println!("  --max-files <N>         Refuse to run if more than N targets were given.");
+    println!("  -y, --yes, --assume-yes Answer yes to every confirmation (e.g. --max-files).");
+    println!("  --strict                Treat warnings as errors: fail the target.");
+    println!("  --strict-at-end         Process everything, but exit non-zero on any warning.");
+    println!("  --fail-fast             Stop at the first target that fails.");
+    println!("  --transactional         Restore all modified targets if any target fails.");
+    println!("  --keep-going            Continue past failed targets (default).");
+    println!("  --no-match <append|error>");
+    println!(
+        "                          If no line matches: append with a warning (default) or fail."
+    );
+    println!("  --help                  Show this message.");
+    println!("\n{}Commands:{}", BLUE, RESET);
+    println!("  doctor <filename> [text] [--format <text|json>]");
+    println!("                          Diagnose a target without modifying it.");
+    println!("                          To prepend to a file named doctor, use ./doctor");
+    println!("                          or put -- before it.");
+    println!("\n{}Environment:{}", BLUE, RESET);
+    println!("  PREPEND_TEXT            Text to use when no text argument is given.");
 }
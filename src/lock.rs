@@ -0,0 +1,87 @@
+//! Advisory locking of target files.
+//!
+//! Atomic rename protects readers, but two writers prepending to the same
+//! file (e.g. cron jobs on several hosts sharing a mount) both read the
+//! original and the last rename wins, losing one header. With locking, the
+//! source is locked before it is read and the lock is held through the
+//! rename, which serializes concurrent runs.
+//!
+//! Locks are advisory: they only coordinate processes that also lock.
+
+use crate::error::PrependError;
+use std::fs::{File, TryLockError};
+use std::path::Path;
+
+/// What to do about locking the target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Don't lock
+    #[default]
+    None,
+    /// Lock, failing with [`PrependError::Locked`] if another process holds it
+    Fail,
+    /// Lock, waiting for another process to release it
+    Wait,
+    /// Lock, skipping the file if another process holds it
+    ///
+    /// The operation still returns [`PrependError::Locked`]; callers report
+    /// it as skipped rather than failed.
+    Skip,
+}
+
+/// Opens the source file for reading, locking it according to `mode`.
+///
+/// The returned handle holds the lock until it is dropped. The source must
+/// be read through this handle, since on some platforms locks are mandatory
+/// and block other handles from reading.
+///
+/// # Arguments
+///
+/// * `path` - File to open
+/// * `mode` - How to lock it
+///
+/// # Returns
+///
+/// * `Ok(File)` - The opened (and possibly locked) source
+/// * `Err(PrependError)` - The file is locked elsewhere or could not be opened
+pub fn open_source(path: &Path, mode: LockMode) -> Result<File, PrependError> {
+    loop {
+        let file = File::open(path)?;
+        match mode {
+            LockMode::None => return Ok(file),
+            LockMode::Wait => file.lock()?,
+            LockMode::Fail | LockMode::Skip => match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    return Err(PrependError::Locked(format!("{:?}", path)));
+                }
+                Err(TryLockError::Error(e)) => return Err(PrependError::Io(e)),
+            },
+        }
+
+        // Whoever held the lock may have renamed a new file into place while
+        // we waited, in which case we locked the old one; retry on the new one.
+        if still_at_path(&file, path) {
+            return Ok(file);
+        }
+    }
+}
+
+/// Returns true if `path` still refers to the open `file`.
+#[cfg(unix)]
+fn still_at_path(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Returns true if `path` still refers to the open `file`.
+///
+/// File identity isn't available here, so the lock is trusted as-is.
+#[cfg(not(unix))]
+fn still_at_path(_file: &File, _path: &Path) -> bool {
+    true
+}
@@ -1,60 +1,684 @@
-use prepend::constants::{GREEN, RED, RESET, YELLOW};
-use prepend::{parse_arguments, perform_prepend, validate_file};
+use prepend::confirm::confirm;
+use prepend::constants::{GREEN, PREVIEW_CONTEXT_LINES, RED, RESET, YELLOW};
+use prepend::doctor::{diagnose, parse_doctor_arguments};
+use prepend::error::PrependError;
+use prepend::insert::InsertPosition;
+use prepend::json::{self, JsonObject};
+use prepend::lock::LockMode;
+use prepend::managed::find_block;
+use prepend::manifest::{ManifestEntry, write_manifest};
+use prepend::patch::patch_for;
+use prepend::status::StatusEvents;
+use prepend::transaction::backup_path_for;
+use prepend::warning::{Strictness, Warning, WarningCode};
+use prepend::{
+    Config, OutputFormat, TargetOutcome, parse_arguments_confirming, perform_prepend,
+    preview_insertion, rename_is_atomic, render_header, run_batch, select_targets, skip_reason,
+    text_warnings, validate_metadata_only, validate_target, would_change,
+};
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{Duration, Instant};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    // Parse arguments
-    let config = parse_arguments(&args).unwrap_or_else(|err| {
+    if args.get(1).map(String::as_str) == Some("doctor") {
+        run_doctor(&args[2..]);
+        return;
+    }
+
+    // Parse arguments; going past --max-files needs a confirmation
+    let config = parse_arguments_confirming(&args, confirmed).unwrap_or_else(|err| {
+        eprintln!("{}ERROR:{} {}", RED, RESET, err);
+        process::exit(1);
+    });
+
+    let mut run = Run::default();
+    if let Some(fd) = config.status_fd {
+        run.status = StatusEvents::open(fd).unwrap_or_else(|err| {
+            eprintln!("{}ERROR:{} --status-fd {}: {}", RED, RESET, fd, err);
+            process::exit(1);
+        });
+    }
+
+    // Duplicate, stale and untracked targets are reported and left out
+    let selected = select_targets(&config, |path, reason| {
+        // Every finished event is paired with a started one
+        run.status.started(path);
+        run.record(manifest_entry(path, "skipped"));
+        match config.format {
+            OutputFormat::Text => {
+                let why = match reason {
+                    "duplicate" => "is a duplicate of another target",
+                    "not_modified" => "was not modified since the cutoff",
+                    _ => "is not tracked by git",
+                };
+                println!("{}SKIPPED:{} {:?} {}", YELLOW, RESET, path, why);
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                file_json(path, "skipped", &[])
+                    .string("reason", reason)
+                    .render()
+            ),
+        }
+    });
+    let targets = selected.unwrap_or_else(|err| {
         eprintln!("{}ERROR:{} {}", RED, RESET, err);
         process::exit(1);
     });
 
-    // Validate file
-    if let Err(e) = validate_file(&config.filename) {
-        eprintln!("{}ERROR:{} {}", RED, RESET, e);
+    if let Some(patch_path) = &config.emit_patch {
+        emit_patch(&config, &targets, patch_path);
+        return;
+    }
+
+    // Process every target, continuing past per-file failures unless
+    // --fail-fast; with --transactional every target is backed up first so a
+    // failure can undo the whole batch
+    let batch = run_batch(&config, &targets, |target| process_target(target, &mut run))
+        .unwrap_or_else(|err| {
+            eprintln!(
+                "{}FATAL ERROR:{} Could not back up targets: {}",
+                RED, RESET, err
+            );
+            process::exit(1);
+        });
+    let mut failed = batch.failed;
+    if batch.aborted {
+        let remaining = batch.not_processed.len();
+        if remaining > 0 && config.format == OutputFormat::Text {
+            let reason = if config.fail_fast {
+                "--fail-fast"
+            } else {
+                "--transactional"
+            };
+            eprintln!(
+                "{}ABORTED:{} {} set; {} remaining target(s) not processed",
+                RED, RESET, reason, remaining
+            );
+        }
+        for target in &batch.not_processed {
+            run.status.started(target);
+            run.record(manifest_entry(target, "not_processed"));
+        }
+    }
+
+    if let Some((restored, failures)) = batch.rollback {
+        let unrestored = report_rollback(&config, restored, failures);
+        for entry in &mut run.manifest {
+            if entry.status == "success" && !unrestored.contains(&entry.file) {
+                entry.status = "rolled_back".to_string();
+                entry.new_size = None;
+            }
+        }
+    }
+    if config.transactional && !config.dry_run {
+        // Only backups that could not be restored are left behind
+        for entry in &mut run.manifest {
+            let backup = backup_path_for(&entry.file);
+            entry.backup = backup.exists().then_some(backup);
+        }
+    }
+
+    // Written even when targets failed, so the failures are on record too
+    if let Some(path) = &config.manifest
+        && let Err(e) = write_manifest(path, &run.manifest, config.manifest_format)
+    {
+        eprintln!(
+            "{}ERROR:{} Could not write manifest {:?}: {}",
+            RED, RESET, path, e
+        );
+        failed = true;
+    }
+
+    if config.time && run.totals.files > 1 {
+        report_total_time(&config, &run.totals);
+    }
+
+    if run.warned && config.strict == Strictness::FailAtEnd {
+        if config.format == OutputFormat::Text {
+            eprintln!(
+                "{}ERROR:{} warnings were raised and --strict-at-end is set",
+                RED, RESET
+            );
+        }
         process::exit(1);
     }
+    // A dry run doubles as an "is everything up to date?" probe
+    if failed || (config.dry_run && run.changed) {
+        process::exit(1);
+    }
+}
+
+/// Asks whether to process `found` targets despite `--max-files <cap>`.
+fn confirmed(found: usize, cap: usize) -> bool {
+    let question = format!(
+        "Process all {} files (more than --max-files {})?",
+        found, cap
+    );
+    confirm(&question).unwrap_or(false)
+}
+
+/// Reports the rollback of a failed transactional batch, returning the
+/// targets that could not be restored.
+fn report_rollback(
+    config: &Config,
+    restored: usize,
+    failures: Vec<(PathBuf, PrependError)>,
+) -> Vec<PathBuf> {
+    for (path, err) in &failures {
+        eprintln!(
+            "{}FATAL ERROR:{} Could not restore {:?}: {} (backup kept at {:?})",
+            RED,
+            RESET,
+            path,
+            err,
+            backup_path_for(path)
+        );
+    }
+    match config.format {
+        OutputFormat::Json => println!(
+            "{}",
+            JsonObject::report()
+                .string("status", "rolled_back")
+                .number("restored", restored)
+                .number("failed", failures.len())
+                .render()
+        ),
+        OutputFormat::Text => println!(
+            "{}ROLLED BACK:{} {} modified file(s) restored to their originals",
+            YELLOW, RESET, restored
+        ),
+    }
+    failures.into_iter().map(|(path, _)| path).collect()
+}
+
+/// Runs the `doctor` subcommand and exits non-zero if the target is unusable.
+fn run_doctor(args: &[String]) {
+    let options = parse_doctor_arguments(args).unwrap_or_else(|err| {
+        eprintln!("{}ERROR:{} {}", RED, RESET, err);
+        process::exit(1);
+    });
+
+    let report = diagnose(&options.path, options.header.as_deref());
+    match options.format {
+        OutputFormat::Text => print!("{}", report.to_text()),
+        OutputFormat::Json => println!("{}", report.to_json()),
+    }
+
+    if !report.is_healthy() {
+        process::exit(1);
+    }
+}
+
+/// Writes a single patch covering every target instead of modifying them.
+fn emit_patch(config: &Config, targets: &[PathBuf], patch_path: &Path) {
+    let mut patch = Vec::new();
+    let mut failed = false;
+
+    for target in targets {
+        let target_config = config.for_target(target);
+        // A skipped target gets no hunk, as a real run wouldn't touch it
+        if let Some(reason) = skip_reason(&target_config) {
+            println!(
+                "{}SKIPPED:{} {:?} {}",
+                YELLOW,
+                RESET,
+                target,
+                skip_message(reason)
+            );
+            continue;
+        }
+        let diff = validate_metadata_only(target, config.require_extension).and_then(|warnings| {
+            print_warnings(config, &warnings);
+            patch_for(&target_config)
+        });
+        match diff {
+            Ok(diff) => patch.extend_from_slice(&diff),
+            Err(e) => {
+                eprintln!("{}ERROR:{} {}", RED, RESET, e);
+                failed = true;
+            }
+        }
+    }
+
+    if let Err(e) = fs::write(patch_path, &patch) {
+        eprintln!("{}FATAL ERROR:{} {}", RED, RESET, e);
+        process::exit(1);
+    }
+    println!(
+        "{}SUCCESS:{} Patch written to {:?}",
+        GREEN, RESET, patch_path
+    );
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Describes a [`skip_reason`] for the text output.
+fn skip_message(reason: &str) -> &'static str {
+    match reason {
+        "empty" => "is empty",
+        "unchanged" => "already has this header (hash matches)",
+        _ => "already starts with the text",
+    }
+}
+
+/// State carried from one target of a run to the next
+#[derive(Default)]
+struct Run {
+    /// Totals for `--time`
+    totals: Timing,
+    /// Set once any warning is raised, for `--strict-at-end`
+    warned: bool,
+    /// Set once a dry run finds a target that would change
+    changed: bool,
+    /// One entry per target, for `--write-manifest`
+    manifest: Vec<ManifestEntry>,
+    /// Progress events for `--status-fd`
+    status: StatusEvents,
+}
+
+impl Run {
+    /// Records the outcome of a target.
+    fn record(&mut self, entry: ManifestEntry) {
+        self.status.finished(&entry);
+        self.manifest.push(entry);
+    }
+}
+
+/// Bytes written and time spent across prepended files, for `--time`
+#[derive(Default)]
+struct Timing {
+    files: usize,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+impl Timing {
+    fn duration_ms(&self) -> f64 {
+        self.elapsed.as_secs_f64() * 1000.0
+    }
+
+    /// Throughput in MB/s (10^6 bytes), based on the size of the result.
+    fn throughput_mbps(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.bytes as f64 / 1_000_000.0 / secs
+    }
+}
+
+/// Prints the aggregate timing of a batch.
+fn report_total_time(config: &Config, totals: &Timing) {
+    match config.format {
+        OutputFormat::Json => println!(
+            "{}",
+            JsonObject::report()
+                .string("status", "summary")
+                .number("files", totals.files)
+                .number("duration_ms", format!("{:.3}", totals.duration_ms()))
+                .number(
+                    "throughput_mbps",
+                    format!("{:.3}", totals.throughput_mbps())
+                )
+                .render()
+        ),
+        OutputFormat::Text => println!(
+            "{}TIME:{} {} files in {:.3} ms ({:.3} MB/s)",
+            YELLOW,
+            RESET,
+            totals.files,
+            totals.duration_ms(),
+            totals.throughput_mbps()
+        ),
+    }
+}
+
+/// Validates and prepends (or previews) a single target.
+///
+/// The outcome is recorded in `run` and returned.
+fn process_target(config: &Config, run: &mut Run) -> TargetOutcome {
+    run.status.started(&config.filename);
+
+    // Check access without opening the file, so its access time is untouched
+    let validated = validate_target(config);
+    if validated
+        .as_ref()
+        .is_ok_and(|warnings| !warnings.is_empty())
+    {
+        run.warned = true;
+    }
+    let mut warnings = match validated.and_then(|warnings| config.strict.enforce(warnings)) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            report_failure(run, config, &[], "ERROR", &e);
+            return TargetOutcome::Failed;
+        }
+    };
+    if config.print_temp_path && config.format == OutputFormat::Text {
+        println!("TEMP PATH: {:?}", config.temp_path());
+    }
+    if let Some(reason) = skip_reason(config) {
+        match config.format {
+            OutputFormat::Json => println!(
+                "{}",
+                target_json(config, "skipped", &warnings)
+                    .string("reason", reason)
+                    .render()
+            ),
+            OutputFormat::Text => println!(
+                "{}SKIPPED:{} {:?} {}",
+                YELLOW,
+                RESET,
+                config.filename,
+                skip_message(reason)
+            ),
+        }
+        run.record(manifest_entry(&config.filename, "skipped"));
+        return TargetOutcome::Skipped;
+    }
 
     // Execution
     if config.dry_run {
+        let text = match render_header(config) {
+            Ok(text) => {
+                let found = text_warnings(&text);
+                run.warned |= !found.is_empty();
+                if let Err(e) = config.strict.enforce(found.clone()) {
+                    report_failure(run, config, &warnings, "ERROR", &e);
+                    return TargetOutcome::Failed;
+                }
+                warnings.extend(found);
+                text
+            }
+            Err(e) => {
+                report_failure(run, config, &warnings, "ERROR", &e);
+                return TargetOutcome::Failed;
+            }
+        };
+        let previewed = if config.only_added {
+            print_added_lines(run, config, &text, &warnings)
+        } else {
+            print_preview(run, config, &text, &warnings)
+        };
+        if previewed {
+            // Anything unclear counts as a change, so the probe errs on the safe side
+            run.changed |= would_change(config).unwrap_or(true);
+            run.record(manifest_entry(&config.filename, "dry_run"));
+            TargetOutcome::Skipped
+        } else {
+            TargetOutcome::Failed
+        }
+    } else {
+        print_warnings(config, &warnings);
+        // Only the call itself is timed
+        let started = Instant::now();
+        let result = perform_prepend(config);
+        let elapsed = started.elapsed();
+
+        match result {
+            Ok(stats) => {
+                let timing = Timing {
+                    files: 1,
+                    bytes: stats.new_size,
+                    elapsed,
+                };
+                run.totals.files += 1;
+                run.totals.bytes += timing.bytes;
+                run.totals.elapsed += timing.elapsed;
+
+                run.warned |= !stats.warnings.is_empty();
+                print_warnings(config, &stats.warnings);
+                let warnings = [warnings, stats.warnings].concat();
+                match config.format {
+                    OutputFormat::Json => {
+                        let mut report = target_json(config, "success", &warnings);
+                        if let Some(output) = &config.output {
+                            report = report.string("output", &output.to_string_lossy());
+                        }
+                        if let Some(lines) = stats.lines {
+                            report = report.number("lines", lines);
+                        }
+                        if config.time {
+                            report = report
+                                .number("duration_ms", format!("{:.3}", timing.duration_ms()))
+                                .number(
+                                    "throughput_mbps",
+                                    format!("{:.3}", timing.throughput_mbps()),
+                                );
+                        }
+                        println!("{}", report.render());
+                    }
+                    OutputFormat::Text => match &config.output {
+                        Some(output) => println!(
+                            "{}SUCCESS:{} Result of {:?} written to {:?}",
+                            GREEN, RESET, config.filename, output
+                        ),
+                        None => println!(
+                            "{}SUCCESS:{} Text prepended to {:?}",
+                            GREEN, RESET, config.filename
+                        ),
+                    },
+                }
+                if let (Some(lines), OutputFormat::Text) = (stats.lines, config.format) {
+                    println!("  lines: {}", lines);
+                }
+                if config.time && config.format == OutputFormat::Text {
+                    println!(
+                        "  time: {:.3} ms ({:.3} MB/s)",
+                        timing.duration_ms(),
+                        timing.throughput_mbps()
+                    );
+                }
+                run.record(ManifestEntry {
+                    old_size: Some(stats.original_size),
+                    new_size: Some(stats.new_size),
+                    ..ManifestEntry::new(&config.filename, "success")
+                });
+                TargetOutcome::Modified
+            }
+            Err(e @ PrependError::Locked(_))
+                if config.lock == LockMode::Skip && config.strict != Strictness::Fail =>
+            {
+                run.warned = true;
+                let mut warnings = warnings;
+                warnings.push(Warning::new(WarningCode::LockedFileSkipped, e.to_string()));
+                match config.format {
+                    OutputFormat::Json => println!(
+                        "{}",
+                        target_json(config, "skipped", &warnings)
+                            .string("reason", "locked")
+                            .render()
+                    ),
+                    OutputFormat::Text => {
+                        println!("{}SKIPPED:{} {}", YELLOW, RESET, warnings.last().unwrap())
+                    }
+                }
+                run.record(manifest_entry(&config.filename, "skipped"));
+                TargetOutcome::Skipped
+            }
+            Err(e) => {
+                run.warned |= matches!(e, PrependError::WarningAsError(_));
+                report_failure(run, config, &warnings, "FATAL ERROR", &e);
+                TargetOutcome::Failed
+            }
+        }
+    }
+}
+
+/// Prints what a dry run would write to the target: the text and the start
+/// of the file, or the lines around the insertion point away from the top.
+/// Returns false if the preview couldn't be produced.
+fn print_preview(run: &mut Run, config: &Config, text: &str, warnings: &[Warning]) -> bool {
+    let atomic = rename_is_atomic(config);
+    if config.format == OutputFormat::Json {
         println!(
-            "{}DRY-RUN MODE:{} The following would be written to {:?}:",
-            YELLOW, RESET, config.filename
+            "{}",
+            target_json(config, "dry_run", warnings)
+                .string("text", text)
+                .bool("atomic", atomic)
+                .render()
         );
-        println!("----------------------------------------------");
-        println!(
-            "{}{}",
-            config.prepend_text,
-            if config.prepend_text.ends_with('\n') {
-                ""
-            } else {
-                "\n"
+        return true;
+    }
+    print_warnings(config, warnings);
+    println!(
+        "{}DRY-RUN MODE:{} The following would be written to {:?}:",
+        YELLOW, RESET, config.filename
+    );
+    if atomic {
+        println!("atomic: true");
+    } else {
+        println!("atomic: false (the final rename would cross filesystems)");
+    }
+    println!("----------------------------------------------");
+    if !matches!(config.position, InsertPosition::Top) {
+        // Away from the top, show where the text lands between the original lines
+        match preview_insertion(config, PREVIEW_CONTEXT_LINES) {
+            Ok(preview) => {
+                for line in &preview.before {
+                    println!("{}", line);
+                }
+                println!("{}>>> inserted{}", GREEN, RESET);
+                for line in &preview.inserted {
+                    println!("{}{}{}", GREEN, line, RESET);
+                }
+                println!("{}<<<{}", GREEN, RESET);
+                for line in &preview.after {
+                    println!("{}", line);
+                }
             }
-        );
-        // In dry run, we just peek at the first few lines of the file to show context
-        if let Ok(file) = File::open(&config.filename) {
-            let mut handle = file.take(200); // Read only first 200 bytes for preview
-            let mut buffer = String::new();
-            if handle.read_to_string(&mut buffer).is_ok() {
-                println!("{}... (Original Content) ...{}", buffer, RESET);
+            Err(e) => {
+                report_failure(run, config, warnings, "ERROR", &e);
+                return false;
             }
         }
         println!("----------------------------------------------");
+        return true;
+    }
+    println!("{}{}", text, if text.ends_with('\n') { "" } else { "\n" });
+    // In dry run, we just peek at the first few lines of the file to show context
+    if let Ok(mut file) = File::open(&config.filename) {
+        // A managed block at the top is replaced, so the original starts after it
+        let comment = config.hash_marker.as_deref();
+        if let Some(block) = comment.and_then(|c| find_block(&config.filename, c).ok().flatten()) {
+            println!("(replaces the {}-byte managed block at the top)", block.len);
+            let _ = file.seek(SeekFrom::Start(block.len));
+        }
+        let mut handle = file.take(200); // Read only first 200 bytes for preview
+        let mut buffer = String::new();
+        if handle.read_to_string(&mut buffer).is_ok() {
+            println!("{}... (Original Content) ...{}", buffer, RESET);
+        }
+    }
+    println!("----------------------------------------------");
+    true
+}
+
+/// Prints only the lines a dry run would insert, under a `==> file <==`
+/// heading (or as `added_lines` in JSON). Returns false if they couldn't be
+/// determined.
+fn print_added_lines(run: &mut Run, config: &Config, text: &str, warnings: &[Warning]) -> bool {
+    let lines = if matches!(config.position, InsertPosition::Top) {
+        text.lines().map(str::to_string).collect()
     } else {
-        match perform_prepend(&config) {
-            Ok(_) => println!(
-                "{}SUCCESS:{} Text prepended to {:?}",
-                GREEN, RESET, config.filename
-            ),
+        // A created section header is part of what gets added
+        match preview_insertion(config, 0) {
+            Ok(preview) => preview.inserted,
             Err(e) => {
-                eprintln!("{}FATAL ERROR:{} {}", RED, RESET, e);
-                process::exit(1);
+                report_failure(run, config, warnings, "ERROR", &e);
+                return false;
+            }
+        }
+    };
+    match config.format {
+        OutputFormat::Json => println!(
+            "{}",
+            target_json(config, "dry_run", warnings)
+                .raw(
+                    "added_lines",
+                    json::array(lines.iter().map(|line| json::escape(line)))
+                )
+                .render()
+        ),
+        OutputFormat::Text => {
+            print_warnings(config, warnings);
+            println!("==> {} <==", config.filename.display());
+            for line in &lines {
+                println!("{}", line);
             }
         }
     }
+    true
+}
+
+/// Prints warnings in text mode; JSON reports carry them inline instead.
+fn print_warnings(config: &Config, warnings: &[Warning]) {
+    if config.format == OutputFormat::Text {
+        for warning in warnings {
+            println!("{}WARNING:{} {}", YELLOW, RESET, warning);
+        }
+    }
+}
+
+/// Reports a failed target on stderr (text) or stdout (JSON) and records it
+/// in `run`.
+fn report_failure(
+    run: &mut Run,
+    config: &Config,
+    warnings: &[Warning],
+    label: &str,
+    error: &PrependError,
+) {
+    run.record(ManifestEntry {
+        error: Some(error.to_string()),
+        ..manifest_entry(&config.filename, "error")
+    });
+    match config.format {
+        OutputFormat::Text => eprintln!("{}{}:{} {}", RED, label, RESET, error),
+        OutputFormat::Json => println!(
+            "{}",
+            target_json(config, "error", warnings)
+                .string("error", &error.to_string())
+                .render()
+        ),
+    }
+}
+
+/// Like [`file_json`] for a processed target, adding `temp_path` with
+/// `--print-temp-path`.
+fn target_json(config: &Config, status: &str, warnings: &[Warning]) -> JsonObject {
+    let report = file_json(&config.filename, status, warnings);
+    if config.print_temp_path {
+        report.string("temp_path", &config.temp_path().to_string_lossy())
+    } else {
+        report
+    }
+}
+
+/// Starts the JSON report for one file.
+fn file_json(path: &Path, status: &str, warnings: &[Warning]) -> JsonObject {
+    JsonObject::report()
+        .string("file", &path.to_string_lossy())
+        .string("status", status)
+        .raw(
+            "warnings",
+            json::array(warnings.iter().map(Warning::to_json)),
+        )
+}
+
+/// Starts a manifest entry for `path`, with its current size as the old size.
+fn manifest_entry(path: &Path, status: &str) -> ManifestEntry {
+    ManifestEntry {
+        old_size: fs::metadata(path).ok().map(|metadata| metadata.len()),
+        ..ManifestEntry::new(path, status)
+    }
 }
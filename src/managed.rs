@@ -0,0 +1,128 @@
+//! Hash-anchored managed headers for `--hash-marker`.
+//!
+//! The text is written as a block that ends in a marker line such as
+//! `# prepend-hash: 9a3c0f6e12b4d587`. The value is the 64-bit FNV-1a hash,
+//! as 16 lowercase hex digits, of everything in the block before the marker
+//! line. A later run only has to find the marker: the same hash means the
+//! header is current and the target is skipped, a different one means the
+//! block, marker line included, is replaced by the new one. A marker only
+//! counts if it is the hash of the lines above it, so a line that merely
+//! looks like one (or a block edited by hand) never gets content removed.
+//!
+//! FNV-1a is not a cryptographic hash. It only has to notice that the header
+//! changed, not resist someone forging a match. The marker is looked for in
+//! the first [`SNIFF_SIZE`] bytes, so a managed block must fit in them.
+//!
+//! [`SNIFF_SIZE`]: crate::constants::SNIFF_SIZE
+
+use crate::detect;
+use crate::error::PrependError;
+use crate::resume::fnv1a_64;
+use crate::{Config, render_header};
+use std::io;
+use std::path::Path;
+
+/// Word between the comment and the hash in a marker line
+const TAG: &str = "prepend-hash:";
+
+/// The managed block at the top of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedBlock {
+    /// Length of the block in bytes, up to and including the marker line
+    pub len: u64,
+    /// The hash recorded in the marker
+    pub hash: String,
+}
+
+/// Returns the hash the marker records for a block starting with `body`.
+pub fn header_hash(body: &[u8]) -> String {
+    format!("{:016x}", fnv1a_64(body))
+}
+
+/// Ends `text` with its marker line, written after `comment` (e.g. `#` or
+/// `//`).
+///
+/// A newline is added first if `text` doesn't end with one, and the marker
+/// uses the text's line ending. Text that already ends with its own marker
+/// is returned unchanged, so rendering twice doesn't stack markers.
+pub fn mark(text: &str, comment: &str) -> String {
+    if trailing_marker(text.as_bytes(), comment).is_some() {
+        return text.to_string();
+    }
+    let mut body = text.to_string();
+    if !body.ends_with('\n') {
+        body.push('\n');
+    }
+    let ending = if body.ends_with("\r\n") { "\r\n" } else { "\n" };
+    let hash = header_hash(body.as_bytes());
+    format!("{}{} {} {}{}", body, comment, TAG, hash, ending)
+}
+
+/// Finds the managed block at the top of the file at `path`: everything up
+/// to and including the first marker line for `comment` whose hash matches
+/// the content above it.
+pub fn find_block(path: &Path, comment: &str) -> io::Result<Option<ManagedBlock>> {
+    let prefix = detect::sniff(path)?;
+    let mut len = 0;
+    for line in prefix.split_inclusive(|&b| b == b'\n') {
+        let start = len;
+        len += line.len();
+        if let Some(hash) = marker_hash(line, comment)
+            && hash == header_hash(&prefix[..start])
+        {
+            return Ok(Some(ManagedBlock {
+                len: len as u64,
+                hash: hash.to_string(),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns true if the target of `config` already has the rendered text as
+/// its managed block, judged by the hashes alone.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether the recorded hash matches the new header's;
+///   always false without `hash_marker`
+/// * `Err(PrependError)` - The target could not be read
+pub fn is_current(config: &Config) -> Result<bool, PrependError> {
+    let Some(comment) = &config.hash_marker else {
+        return Ok(false);
+    };
+    let Some(block) = find_block(&config.filename, comment)? else {
+        return Ok(false);
+    };
+    let header = render_header(config)?;
+    Ok(trailing_marker(header.as_bytes(), comment) == Some(block.hash.as_str()))
+}
+
+/// Returns the hash of the marker `text` ends with, if that marker is valid
+/// for the rest of `text`.
+fn trailing_marker<'a>(text: &'a [u8], comment: &str) -> Option<&'a str> {
+    let start = text
+        .strip_suffix(b"\n")?
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let hash = marker_hash(&text[start..], comment)?;
+    (hash == header_hash(&text[..start])).then_some(hash)
+}
+
+/// Parses a complete marker line such as `# prepend-hash: 9a3c0f6e12b4d587\n`,
+/// returning the hash.
+fn marker_hash<'a>(line: &'a [u8], comment: &str) -> Option<&'a str> {
+    let line = line.strip_suffix(b"\n")?;
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let hash = line
+        .strip_prefix(comment.as_bytes())?
+        .strip_prefix(b" ")?
+        .strip_prefix(TAG.as_bytes())?
+        .strip_prefix(b" ")?;
+    let valid = hash.len() == 16
+        && hash
+            .iter()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b));
+    valid.then(|| std::str::from_utf8(hash).ok()).flatten()
+}
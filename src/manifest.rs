@@ -0,0 +1,149 @@
+//! Run manifests for `--write-manifest`.
+//!
+//! A manifest lists every target a run dealt with, including the ones that
+//! failed or were never reached, so a batch can be audited (or retried)
+//! after the fact.
+
+use crate::error::PrependError;
+use crate::json::{self, JsonObject};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File format of a manifest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// A JSON object with a `files` array
+    #[default]
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl FromStr for ManifestFormat {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(ManifestFormat::Json),
+            "csv" => Ok(ManifestFormat::Csv),
+            _ => Err(PrependError::InvalidValue(
+                "--manifest-format".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+/// What happened to one target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The target
+    pub file: PathBuf,
+    /// The same status as in the JSON report (`success`, `error`, `skipped`,
+    /// `dry_run`), or `rolled_back` / `not_processed`
+    pub status: String,
+    /// Size of the target before the run, if known
+    pub old_size: Option<u64>,
+    /// Size of the result, if it was written
+    pub new_size: Option<u64>,
+    /// Backup of the original that is still on disk, if any
+    pub backup: Option<PathBuf>,
+    /// Why the target failed
+    pub error: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Creates an entry with only the file and status filled in.
+    pub fn new(file: &Path, status: &str) -> Self {
+        ManifestEntry {
+            file: file.to_path_buf(),
+            status: status.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Bytes the run added to the target, if both sizes are known.
+    pub fn bytes_added(&self) -> Option<i64> {
+        Some(self.new_size? as i64 - self.old_size? as i64)
+    }
+
+    fn to_json(&self) -> String {
+        let mut object = JsonObject::new()
+            .string("file", &self.file.to_string_lossy())
+            .string("status", &self.status);
+        if let Some(size) = self.old_size {
+            object = object.number("old_size", size);
+        }
+        if let Some(size) = self.new_size {
+            object = object.number("new_size", size);
+        }
+        if let Some(added) = self.bytes_added() {
+            object = object.number("bytes_added", added);
+        }
+        if let Some(backup) = &self.backup {
+            object = object.string("backup", &backup.to_string_lossy());
+        }
+        if let Some(error) = &self.error {
+            object = object.string("error", error);
+        }
+        object.render()
+    }
+
+    fn to_csv(&self) -> String {
+        let number = |value: Option<u64>| value.map(|n| n.to_string()).unwrap_or_default();
+        let fields = [
+            self.file.to_string_lossy().into_owned(),
+            self.status.clone(),
+            number(self.old_size),
+            number(self.new_size),
+            self.bytes_added()
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            self.backup
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        fields.join(",")
+    }
+}
+
+/// Renders `entries` as a manifest document.
+pub fn render_manifest(entries: &[ManifestEntry], format: ManifestFormat) -> String {
+    match format {
+        ManifestFormat::Json => {
+            let files = json::array(entries.iter().map(ManifestEntry::to_json));
+            format!("{}\n", JsonObject::report().raw("files", files).render())
+        }
+        ManifestFormat::Csv => {
+            let mut csv = String::from("file,status,old_size,new_size,bytes_added,backup,error\n");
+            for entry in entries {
+                csv.push_str(&entry.to_csv());
+                csv.push('\n');
+            }
+            csv
+        }
+    }
+}
+
+/// Writes the manifest for `entries` to `path`, replacing any existing file.
+pub fn write_manifest(
+    path: &Path,
+    entries: &[ManifestEntry],
+    format: ManifestFormat,
+) -> Result<(), PrependError> {
+    fs::write(path, render_manifest(entries, format))?;
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,120 @@
+//! Unified diff generation for the `--emit-patch` mode.
+//!
+//! A prepend only ever touches the start of a file, so the patch is a single
+//! hunk: the new lines followed by a few lines of the original as context.
+//! The output can be consumed by `patch` and `git apply`.
+
+use crate::constants::BUFFER_SIZE;
+use crate::error::PrependError;
+use crate::{Config, render_header};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Number of original lines included as trailing context
+pub const CONTEXT_LINES: usize = 3;
+
+/// Marker emitted after a line that has no trailing newline
+const NO_NEWLINE_MARKER: &[u8] = b"\\ No newline at end of file\n";
+
+/// Builds a unified diff describing the prepend configured in `config`.
+///
+/// Only the first [`CONTEXT_LINES`] lines of the original are read, so this
+/// is cheap even for very large files.
+///
+/// # Arguments
+///
+/// * `config` - Configuration containing the file path and text to prepend
+///
+/// # Returns
+///
+/// * `Ok(Vec<u8>)` - The patch, with `---`/`+++` file headers and one hunk
+/// * `Err(PrependError)` - The original file could not be read
+pub fn patch_for(config: &Config) -> Result<Vec<u8>, PrependError> {
+    let file = File::open(&config.filename)?;
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
+
+    let mut head = Vec::new();
+    for _ in 0..CONTEXT_LINES {
+        if reader.read_until(b'\n', &mut head)? == 0 {
+            break;
+        }
+    }
+
+    let header = render_header(config)?;
+    Ok(unified_diff(&config.filename, &head, header.as_bytes()))
+}
+
+/// Builds a unified diff for prepending `header` to a file starting with `head`.
+///
+/// # Arguments
+///
+/// * `path` - Path used in the `---`/`+++` headers
+/// * `head` - The leading lines of the original file
+/// * `header` - Bytes being prepended
+pub fn unified_diff(path: &Path, head: &[u8], header: &[u8]) -> Vec<u8> {
+    let mut new_content = header.to_vec();
+    new_content.extend_from_slice(head);
+
+    let old_lines: Vec<&[u8]> = head.split_inclusive(|&b| b == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new_content.split_inclusive(|&b| b == b'\n').collect();
+
+    // The original lines survive untouched unless the header lacks a newline
+    // and merges into the first one, so match them up from the end.
+    let common = old_lines
+        .iter()
+        .rev()
+        .zip(new_lines.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let removed = &old_lines[..old_lines.len() - common];
+    let added = &new_lines[..new_lines.len() - common];
+    let context = &old_lines[old_lines.len() - common..];
+
+    let label = path.to_string_lossy().replace('\\', "/");
+    let label = label.trim_start_matches("./");
+    let mut out = Vec::new();
+    out.extend_from_slice(format!("--- a/{}\n+++ b/{}\n", label, label).as_bytes());
+    out.extend_from_slice(
+        format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_lines.len()),
+            hunk_range(new_lines.len())
+        )
+        .as_bytes(),
+    );
+
+    for line in removed {
+        push_line(&mut out, b'-', line);
+    }
+    for line in added {
+        push_line(&mut out, b'+', line);
+    }
+    for line in context {
+        push_line(&mut out, b' ', line);
+    }
+
+    out
+}
+
+/// Formats a hunk range starting at line 1 (or `0,0` for no lines).
+fn hunk_range(count: usize) -> String {
+    if count == 0 {
+        "0,0".to_string()
+    } else {
+        format!("1,{}", count)
+    }
+}
+
+/// Writes one hunk line.
+///
+/// Only the final line of a file can lack a newline, so such a line always
+/// gets the "No newline at end of file" marker.
+fn push_line(out: &mut Vec<u8>, prefix: u8, line: &[u8]) {
+    out.push(prefix);
+    out.extend_from_slice(line);
+    if !line.ends_with(b"\n") {
+        out.push(b'\n');
+        out.extend_from_slice(NO_NEWLINE_MARKER);
+    }
+}
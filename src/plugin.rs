@@ -0,0 +1,186 @@
+//! Custom content transforms for library users.
+//!
+//! A [`ContentTransform`] can rewrite the prepend text and the lines of the
+//! original file. Transforms are added with [`Config::with_transform`] and
+//! run after the built-in text options, in the order they were added.
+//!
+//! [`Config::with_transform`]: crate::Config::with_transform
+
+use crate::detect::{self, Encoding, LineEnding};
+use std::cell::OnceCell;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A step in the content pipeline.
+///
+/// Both methods default to leaving their input alone, so a transform only
+/// implements the part it cares about.
+pub trait ContentTransform: Send + Sync {
+    /// Rewrites the prepend text for the target described by `context`.
+    ///
+    /// The text is UTF-8 going in; invalid UTF-8 in the result is replaced
+    /// with U+FFFD.
+    fn transform_header(&self, header: &[u8], context: &FileContext) -> Vec<u8> {
+        let _ = context;
+        header.to_vec()
+    }
+
+    /// Rewrites one line of the original file, given without its line
+    /// ending (which is kept as it was). `None` keeps the line unchanged.
+    fn transform_line(&self, line: &[u8]) -> Option<Vec<u8>> {
+        let _ = line;
+        None
+    }
+}
+
+/// What a transform may want to know about the target.
+///
+/// Encoding and line ending are detected from the start of the file on
+/// first use, so transforms that don't ask cost nothing.
+#[derive(Debug)]
+pub struct FileContext {
+    path: PathBuf,
+    prefix: OnceCell<Vec<u8>>,
+}
+
+impl FileContext {
+    /// Creates the context for `path`.
+    pub fn new(path: &Path) -> Self {
+        FileContext {
+            path: path.to_path_buf(),
+            prefix: OnceCell::new(),
+        }
+    }
+
+    /// The target file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The target's detected encoding; an unreadable target counts as
+    /// empty, which is UTF-8.
+    pub fn encoding(&self) -> Encoding {
+        detect::detect_encoding(self.prefix())
+    }
+
+    /// The target's detected line-ending style.
+    pub fn line_ending(&self) -> LineEnding {
+        detect::detect_line_ending(self.prefix())
+    }
+
+    fn prefix(&self) -> &[u8] {
+        self.prefix
+            .get_or_init(|| detect::sniff(&self.path).unwrap_or_default())
+    }
+}
+
+/// Hides a transform's header step, for a text that was already rendered.
+pub(crate) struct LinesOnly(pub(crate) Arc<dyn ContentTransform>);
+
+impl ContentTransform for LinesOnly {
+    fn transform_line(&self, line: &[u8]) -> Option<Vec<u8>> {
+        self.0.transform_line(line)
+    }
+}
+
+/// A reader yielding the lines of `inner` after the line transforms, with
+/// their line endings rewritten to `ending` if it is set.
+///
+/// Seeking only supports what the insertion code needs: querying the
+/// current position and rewinding to the start.
+pub(crate) struct TransformedLines<'a, R> {
+    inner: R,
+    transforms: &'a [Arc<dyn ContentTransform>],
+    ending: Option<&'a [u8]>,
+    line: Vec<u8>,
+    consumed: usize,
+    position: u64,
+}
+
+impl<'a, R: BufRead> TransformedLines<'a, R> {
+    pub(crate) fn new(
+        inner: R,
+        transforms: &'a [Arc<dyn ContentTransform>],
+        ending: Option<&'a [u8]>,
+    ) -> Self {
+        TransformedLines {
+            inner,
+            transforms,
+            ending,
+            line: Vec::new(),
+            consumed: 0,
+            position: 0,
+        }
+    }
+
+    /// Reads the next line of `inner` and applies the transforms to it.
+    fn next_line(&mut self) -> io::Result<()> {
+        self.line.clear();
+        self.consumed = 0;
+        if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(());
+        }
+        let ending = if self.line.ends_with(b"\r\n") {
+            2
+        } else {
+            usize::from(self.line.ends_with(b"\n"))
+        };
+        let split = self.line.len() - ending;
+        let mut content = self.line[..split].to_vec();
+        for transform in self.transforms {
+            if let Some(changed) = transform.transform_line(&content) {
+                content = changed;
+            }
+        }
+        match self.ending {
+            Some(ending) if split < self.line.len() => content.extend_from_slice(ending),
+            _ => content.extend_from_slice(&self.line[split..]),
+        }
+        self.line = content;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for TransformedLines<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for TransformedLines<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.consumed == self.line.len() {
+            self.next_line()?;
+        }
+        Ok(&self.line[self.consumed..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed += amount;
+        self.position += amount as u64;
+    }
+}
+
+impl<R: BufRead + Seek> Seek for TransformedLines<'_, R> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        match target {
+            SeekFrom::Current(0) => Ok(self.position),
+            SeekFrom::Start(0) => {
+                self.inner.seek(SeekFrom::Start(0))?;
+                self.line.clear();
+                self.consumed = 0;
+                self.position = 0;
+                Ok(0)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "transformed content can only be rewound",
+            )),
+        }
+    }
+}
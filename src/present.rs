@@ -0,0 +1,99 @@
+//! Detecting text that is already at the top of a file, for
+//! `--skip-if-present`.
+//!
+//! Only as much of the file as the text itself spans is read, so the check
+//! stays cheap on large files.
+
+use crate::constants::BUFFER_SIZE;
+use crate::error::PrependError;
+use crate::{Config, render_header, streams_equal};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the start of the file is compared against the text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMatch {
+    /// The file starts with exactly the bytes of the text
+    #[default]
+    Exact,
+    /// Line by line, ignoring leading and trailing whitespace and the kind of
+    /// line ending
+    Trimmed,
+    /// Exact, except that the first line may differ (e.g. a date stamp); a
+    /// one-line text has nothing else to go by, so it is compared exactly
+    IgnoreFirstLine,
+}
+
+impl FromStr for PresentMatch {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "exact" => Ok(PresentMatch::Exact),
+            "trimmed" => Ok(PresentMatch::Trimmed),
+            "ignore-first-line" => Ok(PresentMatch::IgnoreFirstLine),
+            _ => Err(PrependError::InvalidValue(
+                "--present-match".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+/// Returns true unless the target already starts with the rendered text, as
+/// compared by `config.present_match`.
+///
+/// # Returns
+///
+/// * `Ok(bool)` - Whether prepending would add anything
+/// * `Err(PrependError)` - The target could not be read
+pub fn needs_prepend(config: &Config) -> Result<bool, PrependError> {
+    let header = render_header(config)?;
+    Ok(!starts_with(
+        &config.filename,
+        header.as_bytes(),
+        config.present_match,
+    )?)
+}
+
+/// Reports whether the file at `path` starts with `header` under `mode`.
+pub fn starts_with(path: &Path, header: &[u8], mode: PresentMatch) -> io::Result<bool> {
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, File::open(path)?);
+    match mode {
+        PresentMatch::Exact => prefix_matches(&mut reader, header),
+        PresentMatch::IgnoreFirstLine => {
+            let rest = match header.iter().position(|&b| b == b'\n') {
+                Some(end) => &header[end + 1..],
+                None => &[],
+            };
+            // Otherwise any non-empty file would count as having the text
+            if rest.is_empty() {
+                return prefix_matches(&mut reader, header);
+            }
+            let mut first = Vec::new();
+            if reader.read_until(b'\n', &mut first)? == 0 {
+                return Ok(header.is_empty());
+            }
+            prefix_matches(&mut reader, rest)
+        }
+        PresentMatch::Trimmed => {
+            let mut line = Vec::new();
+            for expected in header.split_inclusive(|&b| b == b'\n') {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0
+                    || line.trim_ascii() != expected.trim_ascii()
+                {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Compares the next `expected.len()` bytes of `reader` with `expected`.
+fn prefix_matches(reader: &mut impl Read, expected: &[u8]) -> io::Result<bool> {
+    streams_equal(reader.take(expected.len() as u64), expected)
+}
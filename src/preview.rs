@@ -0,0 +1,133 @@
+//! Dry-run previews of insertions away from the top of the file.
+//!
+//! The preview is produced by running the normal copy into a
+//! [`PreviewWriter`] with the text wrapped in marker lines. The writer keeps
+//! only the few lines of context before the first marker, the text between
+//! the markers and the context after the second, so memory use stays bounded
+//! like the real copy.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Marks the start of the inserted block. The NUL bytes keep it from ever
+/// matching a real line of a text file.
+const BEGIN_MARKER: &[u8] = b"\0prepend:inserted\0";
+
+/// Marks the end of the inserted block
+const END_MARKER: &[u8] = b"\0prepend:end\0";
+
+/// The lines around an insertion point, without line endings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertionPreview {
+    /// Context lines from just above the inserted text
+    pub before: Vec<String>,
+    /// The inserted lines
+    pub inserted: Vec<String>,
+    /// Context lines from just below the inserted text
+    pub after: Vec<String>,
+}
+
+/// Wraps `header` in the marker lines [`PreviewWriter`] looks for.
+pub(crate) fn mark(header: &[u8]) -> Vec<u8> {
+    let mut marked = [BEGIN_MARKER, b"\n", header].concat();
+    if !header.is_empty() && !header.ends_with(b"\n") {
+        marked.push(b'\n');
+    }
+    marked.extend_from_slice(END_MARKER);
+    marked.push(b'\n');
+    marked
+}
+
+/// Puts `lines` in front of `header`, inside the markers if `header` was
+/// wrapped by [`mark`], so they are previewed as inserted too.
+pub(crate) fn add_before(header: &[u8], lines: &[u8]) -> Vec<u8> {
+    let begin = [BEGIN_MARKER, b"\n"].concat();
+    match header.strip_prefix(begin.as_slice()) {
+        Some(rest) => [begin.as_slice(), lines, rest].concat(),
+        None => [lines, header].concat(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Before,
+    Inserted,
+    After,
+}
+
+/// A writer that collects an [`InsertionPreview`] from output marked with
+/// [`mark`].
+pub(crate) struct PreviewWriter {
+    context: usize,
+    section: Section,
+    partial: Vec<u8>,
+    before: VecDeque<String>,
+    preview: InsertionPreview,
+}
+
+impl PreviewWriter {
+    pub(crate) fn new(context: usize) -> Self {
+        PreviewWriter {
+            context,
+            section: Section::Before,
+            partial: Vec::new(),
+            before: VecDeque::with_capacity(context + 1),
+            preview: InsertionPreview::default(),
+        }
+    }
+
+    /// Returns the preview, counting an unterminated last line.
+    pub(crate) fn finish(mut self) -> InsertionPreview {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.push_line(&line);
+        }
+        self.preview.before = self.before.into();
+        self.preview
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match self.section {
+            Section::Before if line == BEGIN_MARKER => self.section = Section::Inserted,
+            Section::Before => {
+                self.before
+                    .push_back(String::from_utf8_lossy(line).into_owned());
+                if self.before.len() > self.context {
+                    self.before.pop_front();
+                }
+            }
+            Section::Inserted if line == END_MARKER => self.section = Section::After,
+            Section::Inserted => {
+                let line = String::from_utf8_lossy(line).into_owned();
+                self.preview.inserted.push(line);
+            }
+            Section::After => {
+                if self.preview.after.len() < self.context {
+                    let line = String::from_utf8_lossy(line).into_owned();
+                    self.preview.after.push(line);
+                }
+            }
+        }
+    }
+}
+
+impl Write for PreviewWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Nothing more is needed once the trailing context is complete
+        if self.section == Section::After && self.preview.after.len() == self.context {
+            return Ok(buf.len());
+        }
+        self.partial.extend_from_slice(buf);
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            self.push_line(&line);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
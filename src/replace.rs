@@ -0,0 +1,75 @@
+//! Regex rewrites of the text for `--replace-regex`.
+//!
+//! Each rewrite is `<pattern>=<replacement>`, split at the first `=` not
+//! preceded by a backslash (`\=` in the pattern matches a literal `=`). The
+//! replacement uses the `regex` crate's syntax for capture groups (`$1`,
+//! `${name}`), and may also use these per-file variables, filled in for each
+//! target before the groups:
+//!
+//! * `{path}` - the target as given, e.g. `src/net/http.rs`
+//! * `{filename}` - its file name, `http.rs`
+//! * `{stem}` - the file name without its extension, `http`
+//! * `{dir}` - the directory it is in, `src/net` (`.` if none)
+
+use crate::error::PrependError;
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+
+/// One `--replace-regex` rewrite
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    /// What to replace; every match is replaced
+    pub pattern: Regex,
+    /// What to replace it with, before per-file variables are filled in
+    pub replacement: String,
+}
+
+impl FromStr for Replacement {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || PrependError::InvalidValue("--replace-regex".to_string(), value.to_string());
+        let bytes = value.as_bytes();
+        let split = (0..bytes.len())
+            .find(|&i| bytes[i] == b'=' && (i == 0 || bytes[i - 1] != b'\\'))
+            .ok_or_else(invalid)?;
+        let pattern = Regex::new(&value[..split]).map_err(|_| invalid())?;
+        Ok(Replacement {
+            pattern,
+            replacement: value[split + 1..].to_string(),
+        })
+    }
+}
+
+impl Replacement {
+    /// Rewrites every match in `text`, with the variables taken from `target`.
+    pub fn apply(&self, text: &str, target: &Path) -> String {
+        let replacement = expand_path_variables(&self.replacement, target);
+        self.pattern
+            .replace_all(text, replacement.as_str())
+            .into_owned()
+    }
+}
+
+/// Fills in `{path}`, `{filename}`, `{stem}` and `{dir}` for `target`.
+///
+/// The values are escaped, so a `$` in a file name stays literal instead of
+/// being read as a capture group.
+fn expand_path_variables(replacement: &str, target: &Path) -> String {
+    let lossy = |value: Option<&std::ffi::OsStr>| {
+        value
+            .map(|value| value.to_string_lossy().replace('$', "$$"))
+            .unwrap_or_default()
+    };
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => lossy(Some(parent.as_os_str())),
+        _ => ".".to_string(),
+    };
+    replacement
+        .replace("{path}", &lossy(Some(target.as_os_str())))
+        .replace("{filename}", &lossy(target.file_name()))
+        .replace("{stem}", &lossy(target.file_stem()))
+        .replace("{dir}", &dir)
+}
@@ -0,0 +1,200 @@
+//! Checkpointed, resumable prepends for very large files.
+//!
+//! In resumable mode the temp file is kept on failure and a small sidecar
+//! checkpoint records how many bytes of the original have been copied into
+//! it. A later run validates the checkpoint and the partial temp file and, if
+//! both still match, continues copying from the recorded offset instead of
+//! starting over.
+
+use crate::Config;
+use crate::constants::{BUFFER_SIZE, CHECKPOINT_INTERVAL};
+use crate::error::PrependError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// First word of every checkpoint file, including the format version
+const CHECKPOINT_MAGIC: &str = "prepend-checkpoint-v1";
+
+/// Progress of an interrupted resumable prepend.
+///
+/// The source length and modification time, and the header length and hash,
+/// tie the checkpoint to one exact operation; if any of them differ on the
+/// next run the checkpoint is discarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Length of the original file in bytes
+    pub source_len: u64,
+    /// Modification time of the original, in nanoseconds since the epoch
+    pub source_modified: u128,
+    /// Length of the prepend text in bytes
+    pub header_len: u64,
+    /// FNV-1a hash of the prepend text
+    pub header_hash: u64,
+    /// Bytes of the original already copied into the temp file
+    pub copied: u64,
+}
+
+impl Checkpoint {
+    /// Describes the operation in `config` after `copied` bytes of the original.
+    pub fn capture(config: &Config, copied: u64) -> io::Result<Checkpoint> {
+        let metadata = fs::metadata(&config.filename)?;
+        let header = config.prepend_text.as_bytes();
+        Ok(Checkpoint {
+            source_len: metadata.len(),
+            source_modified: modified_nanos(&metadata),
+            header_len: header.len() as u64,
+            header_hash: fnv1a_64(header),
+            copied,
+        })
+    }
+
+    /// Writes the checkpoint to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(
+            path,
+            format!(
+                "{} {} {} {} {:016x} {}\n",
+                CHECKPOINT_MAGIC,
+                self.source_len,
+                self.source_modified,
+                self.header_len,
+                self.header_hash,
+                self.copied
+            ),
+        )
+    }
+
+    /// Reads a checkpoint from `path`, returning `None` if it is missing or malformed.
+    pub fn load(path: &Path) -> Option<Checkpoint> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut fields = content.split_whitespace();
+        if fields.next()? != CHECKPOINT_MAGIC {
+            return None;
+        }
+        Some(Checkpoint {
+            source_len: fields.next()?.parse().ok()?,
+            source_modified: fields.next()?.parse().ok()?,
+            header_len: fields.next()?.parse().ok()?,
+            header_hash: u64::from_str_radix(fields.next()?, 16).ok()?,
+            copied: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Returns the checkpoint path used for the target in `config`.
+pub fn checkpoint_path(config: &Config) -> PathBuf {
+    let mut path = config.temp_path().into_os_string();
+    path.push(".progress");
+    PathBuf::from(path)
+}
+
+/// Performs a prepend that can be resumed if it is interrupted.
+///
+/// The temp file is synced and the checkpoint updated every
+/// [`CHECKPOINT_INTERVAL`] bytes, so at most that much work is repeated.
+pub(crate) fn perform_resumable(
+    config: &Config,
+    mut source: File,
+    temp_path: &Path,
+) -> Result<(), PrependError> {
+    let header = config.prepend_text.as_bytes();
+    let checkpoint = checkpoint_path(config);
+
+    let (mut temp, mut copied) = match resume_offset(config, temp_path, &checkpoint)? {
+        Some(copied) => {
+            // Drop anything written after the last checkpoint and carry on from there
+            let mut temp = OpenOptions::new().write(true).open(temp_path)?;
+            temp.set_len(header.len() as u64 + copied)?;
+            temp.seek(SeekFrom::End(0))?;
+            source.seek(SeekFrom::Start(copied))?;
+            (temp, copied)
+        }
+        None => {
+            let mut temp = File::create(temp_path)?;
+            temp.write_all(header)?;
+            temp.sync_data()?;
+            Checkpoint::capture(config, 0)?.save(&checkpoint)?;
+            (temp, 0)
+        }
+    };
+
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut since_checkpoint = 0;
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        temp.write_all(&buffer[..read])?;
+        copied += read as u64;
+        since_checkpoint += read as u64;
+
+        if since_checkpoint >= CHECKPOINT_INTERVAL {
+            // Data must be durable before the checkpoint claims it
+            temp.sync_data()?;
+            Checkpoint::capture(config, copied)?.save(&checkpoint)?;
+            since_checkpoint = 0;
+        }
+    }
+    temp.sync_data()?;
+    drop(temp);
+
+    fs::rename(temp_path, config.destination())?;
+    let _ = fs::remove_file(&checkpoint);
+    // Only release a lock on the source once the new file is in place
+    drop(source);
+    Ok(())
+}
+
+/// Returns the offset to resume from, or `None` to start over.
+///
+/// Resuming requires a checkpoint for this exact source and header, and a
+/// temp file that starts with the header and holds at least the recorded
+/// number of copied bytes.
+fn resume_offset(
+    config: &Config,
+    temp_path: &Path,
+    checkpoint: &Path,
+) -> Result<Option<u64>, PrependError> {
+    let Some(saved) = Checkpoint::load(checkpoint) else {
+        return Ok(None);
+    };
+    let current = Checkpoint::capture(config, saved.copied)?;
+    if saved != current || saved.copied > saved.source_len {
+        return Ok(None);
+    }
+
+    let Ok(mut temp) = File::open(temp_path) else {
+        return Ok(None);
+    };
+    if temp.metadata()?.len() < saved.header_len + saved.copied {
+        return Ok(None);
+    }
+    let mut written_header = vec![0; saved.header_len as usize];
+    temp.read_exact(&mut written_header)?;
+    if written_header != config.prepend_text.as_bytes() {
+        return Ok(None);
+    }
+
+    Ok(Some(saved.copied))
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos())
+}
+
+/// 64-bit FNV-1a hash, stable across Rust versions and platforms.
+pub(crate) fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
@@ -0,0 +1,51 @@
+//! Free-space checks for `--check-space`.
+//!
+//! While a file is rewritten, the original and the temp file exist side by
+//! side, so the temp file needs roughly the projected result size in free
+//! space. Checking up front turns a mid-write `ENOSPC` into a clean refusal.
+
+use crate::error::PrependError;
+use std::io;
+use std::path::Path;
+
+/// Returns the bytes available to unprivileged users on the filesystem
+/// holding `path`, or `None` where this can't be queried (non-Unix).
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is NUL-terminated and `stat` is valid for writes
+    if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so `stat` is initialized
+    let stat = unsafe { stat.assume_init() };
+    // The field types differ between platforms (u32 on some BSDs)
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+/// Returns the bytes available on the filesystem holding `path`, or `None`
+/// where this can't be queried (non-Unix).
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Fails with [`PrependError::InsufficientSpace`] if `required` bytes don't
+/// fit in `available`.
+pub fn check_space(path: &Path, required: u64, available: u64) -> Result<(), PrependError> {
+    if required > available {
+        return Err(PrependError::InsufficientSpace(
+            format!("{:?}", path),
+            required,
+            available,
+        ));
+    }
+    Ok(())
+}
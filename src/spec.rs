@@ -0,0 +1,117 @@
+//! `--spec` strings that name a target and its text together.
+//!
+//! A spec is a `;`-separated list of `key=value` pairs, e.g.
+//! `file=notes.txt;text=Header`. Both `file` and `text` are required, and
+//! everything after the first unescaped `=` is the value. A backslash
+//! escapes the next character, so `\;`, `\=` and `\\` stand for a literal
+//! `;`, `=` and `\`. Empty pairs (such as a trailing `;`) are ignored.
+//!
+//! `--pairs` input is simpler: one `path<delimiter>text` line per target,
+//! with no escaping (see [`parse_pairs`]).
+
+use crate::error::PrependError;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A target file and the text to prepend to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    /// Target file, taken literally (no glob expansion)
+    pub file: PathBuf,
+    /// Text to prepend, exactly as given
+    pub text: String,
+}
+
+impl FromStr for Spec {
+    type Err = PrependError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || PrependError::InvalidValue("--spec".to_string(), spec.to_string());
+
+        let mut file = None;
+        let mut text = None;
+        for pair in split_unescaped(spec, ';').ok_or_else(invalid)? {
+            if pair.is_empty() {
+                continue;
+            }
+            let parts = split_unescaped(&pair, '=').ok_or_else(invalid)?;
+            if parts.len() < 2 {
+                return Err(invalid());
+            }
+            let value = unescape(&parts[1..].join("="));
+            match unescape(&parts[0]).as_str() {
+                "file" => file = Some(PathBuf::from(value)),
+                "text" => text = Some(value),
+                _ => return Err(invalid()),
+            }
+        }
+
+        match (file, text) {
+            (Some(file), Some(text)) => Ok(Spec { file, text }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Splits `input` on unescaped `separator`, keeping escapes in the parts.
+///
+/// Returns `None` if `input` ends with a lone backslash.
+fn split_unescaped(input: &str, separator: char) -> Option<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            current.push(c);
+            current.push(chars.next()?);
+        } else if c == separator {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+    Some(parts)
+}
+
+/// Resolves backslash escapes (already validated by [`split_unescaped`]).
+fn unescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            out.extend(chars.next());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Parses `--pairs` input: one `path<delimiter>text` line per target.
+///
+/// The line is split at the first `delimiter`, so the text may contain
+/// further delimiters. Line endings (`\n` or `\r\n`) are stripped and blank
+/// lines are skipped. A line without a delimiter or with an empty path or
+/// text fails with [`PrependError::MalformedPair`].
+pub fn parse_pairs(input: &str, delimiter: char) -> Result<Vec<Spec>, PrependError> {
+    let mut specs = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let malformed = || PrependError::MalformedPair(index + 1, line.to_string());
+        let (file, text) = line.split_once(delimiter).ok_or_else(malformed)?;
+        if file.is_empty() || text.is_empty() {
+            return Err(malformed());
+        }
+        specs.push(Spec {
+            file: PathBuf::from(file),
+            text: text.to_string(),
+        });
+    }
+    if specs.is_empty() {
+        return Err(PrependError::EmptyInput);
+    }
+    Ok(specs)
+}
@@ -0,0 +1,93 @@
+//! Machine-readable progress events for `--status-fd`.
+//!
+//! Like git's and gpg's status fds, events go to a file descriptor the
+//! caller opened, so a supervising process can follow a long batch without
+//! parsing stdout. Each event is one JSON object per line.
+
+use crate::error::PrependError;
+use crate::json::JsonObject;
+use crate::manifest::ManifestEntry;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::path::Path;
+
+/// Writer for status events; does nothing unless opened on a descriptor
+#[derive(Debug, Default)]
+pub struct StatusEvents {
+    // Never closed: the descriptor belongs to whoever passed it in, and may
+    // even be stdout or stderr
+    out: Option<ManuallyDrop<File>>,
+}
+
+impl StatusEvents {
+    /// Sends events to the already open descriptor `fd`.
+    ///
+    /// Fails if `fd` is not open, or on platforms other than Unix.
+    #[cfg(unix)]
+    pub fn open(fd: i32) -> Result<StatusEvents, PrependError> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: F_GETFD only queries the descriptor's flags
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(PrependError::Io(io::Error::last_os_error()));
+        }
+        // SAFETY: the descriptor is open, and ManuallyDrop keeps the File
+        // from closing it
+        let file = unsafe { File::from_raw_fd(fd) };
+        Ok(StatusEvents {
+            out: Some(ManuallyDrop::new(file)),
+        })
+    }
+
+    /// Sends events to the already open descriptor `fd`.
+    ///
+    /// Fails if `fd` is not open, or on platforms other than Unix.
+    #[cfg(not(unix))]
+    pub fn open(_fd: i32) -> Result<StatusEvents, PrependError> {
+        Err(PrependError::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--status-fd is only supported on Unix",
+        )))
+    }
+
+    /// Reports that work on `file` has begun.
+    pub fn started(&mut self, file: &Path) {
+        self.emit(
+            JsonObject::report()
+                .string("event", "started")
+                .string("file", &file.to_string_lossy()),
+        );
+    }
+
+    /// Reports the outcome of a target: a `failed` event for an error,
+    /// `finished` with the status otherwise.
+    pub fn finished(&mut self, entry: &ManifestEntry) {
+        let file = entry.file.to_string_lossy();
+        let event = match &entry.error {
+            Some(error) => JsonObject::report()
+                .string("event", "failed")
+                .string("file", &file)
+                .string("error", error),
+            None => {
+                let mut event = JsonObject::report()
+                    .string("event", "finished")
+                    .string("file", &file)
+                    .string("status", &entry.status);
+                if let Some(added) = entry.bytes_added() {
+                    event = event.number("bytes_added", added);
+                }
+                event
+            }
+        };
+        self.emit(event);
+    }
+
+    /// Writes one event line. A reader that went away must not fail the
+    /// run, so write errors are ignored.
+    fn emit(&mut self, event: JsonObject) {
+        if let Some(out) = &mut self.out {
+            let _ = writeln!(out, "{}", event.render());
+        }
+    }
+}
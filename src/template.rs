@@ -0,0 +1,52 @@
+//! Placeholders in the text for `--template`.
+//!
+//! `{date}` (`2025-01-31`), `{year}` (`2025`) and `{timestamp}`
+//! (`2025-01-31T12:00:00Z`) are replaced by the time, in UTC, at which the
+//! values were captured. They are captured once per run and shared by every
+//! target, so a batch gets one consistent timestamp no matter how long it
+//! takes. Other text in braces is left as it is.
+
+use crate::since::civil_from_days;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Placeholder values captured at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateValues {
+    /// `{date}`: `YYYY-MM-DD`
+    pub date: String,
+    /// `{year}`: `YYYY`
+    pub year: String,
+    /// `{timestamp}`: RFC 3339 with whole seconds, e.g. `2025-01-31T12:00:00Z`
+    pub timestamp: String,
+}
+
+impl TemplateValues {
+    /// Captures the values for `time`; times before 1970 are clamped to it.
+    pub fn at(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or(0);
+        let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+        let of_day = seconds.rem_euclid(86_400);
+        let date = format!("{:04}-{:02}-{:02}", year, month, day);
+        TemplateValues {
+            timestamp: format!(
+                "{}T{:02}:{:02}:{:02}Z",
+                date,
+                of_day / 3600,
+                of_day / 60 % 60,
+                of_day % 60
+            ),
+            year: year.to_string(),
+            date,
+        }
+    }
+
+    /// Replaces the placeholders in `text`.
+    pub fn expand(&self, text: &str) -> String {
+        text.replace("{date}", &self.date)
+            .replace("{year}", &self.year)
+            .replace("{timestamp}", &self.timestamp)
+    }
+}
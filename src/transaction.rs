@@ -0,0 +1,106 @@
+//! All-or-nothing batches for `--transactional`.
+//!
+//! Every target is copied to a backup next to it before the batch starts.
+//! If any target fails, the ones already modified are restored from their
+//! backups with an atomic rename, and the ones the batch created (see
+//! `--create`) are removed; otherwise the backups are removed.
+
+use crate::error::PrependError;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Backups of a batch's targets, taken before any of them is modified
+#[derive(Debug)]
+pub struct Transaction {
+    /// (target, backup) for every target that existed when the batch began
+    backups: Vec<(PathBuf, PathBuf)>,
+    /// Targets that didn't exist when the batch began
+    absent: Vec<PathBuf>,
+    /// Targets modified so far
+    modified: Vec<PathBuf>,
+}
+
+impl Transaction {
+    /// Backs up every target that is a regular file.
+    ///
+    /// Missing targets are noted, so that rollback removes them if the batch
+    /// creates them. Other targets that aren't regular files are skipped;
+    /// they fail validation later anyway. If a backup can't be taken, the
+    /// ones already taken are removed and the error is returned.
+    pub fn begin(targets: &[PathBuf]) -> Result<Transaction, PrependError> {
+        let mut transaction = Transaction {
+            backups: Vec::new(),
+            absent: Vec::new(),
+            modified: Vec::new(),
+        };
+        transaction.absent = targets
+            .iter()
+            .filter(|t| fs::symlink_metadata(t).is_err())
+            .cloned()
+            .collect();
+        for target in targets.iter().filter(|t| t.is_file()) {
+            let backup = backup_path_for(target);
+            if let Err(e) = fs::copy(target, &backup) {
+                transaction.commit();
+                return Err(PrependError::Io(e));
+            }
+            transaction.backups.push((target.clone(), backup));
+        }
+        Ok(transaction)
+    }
+
+    /// Records that `target` has been modified and must be restored on
+    /// rollback.
+    pub fn record(&mut self, target: &Path) {
+        self.modified.push(target.to_path_buf());
+    }
+
+    /// Restores every recorded target from its backup, removes the recorded
+    /// targets that didn't exist before, and removes the remaining backups.
+    ///
+    /// A failed restore doesn't stop the others; its backup is left in place
+    /// for manual recovery. Returns the number of targets restored and the
+    /// ones that could not be.
+    pub fn rollback(self) -> (usize, Vec<(PathBuf, PrependError)>) {
+        let mut restored = 0;
+        let mut failures = Vec::new();
+        for (target, backup) in self.backups {
+            if !self.modified.contains(&target) {
+                let _ = fs::remove_file(&backup);
+                continue;
+            }
+            match fs::rename(&backup, &target) {
+                Ok(_) => restored += 1,
+                Err(e) => failures.push((target, PrependError::Io(e))),
+            }
+        }
+        for target in self.absent {
+            if !self.modified.contains(&target) {
+                continue;
+            }
+            match fs::remove_file(&target) {
+                Ok(_) => restored += 1,
+                Err(e) => failures.push((target, PrependError::Io(e))),
+            }
+        }
+        (restored, failures)
+    }
+
+    /// Keeps all changes and removes the backups.
+    pub fn commit(self) {
+        for (_, backup) in &self.backups {
+            let _ = fs::remove_file(backup);
+        }
+    }
+}
+
+/// Returns the backup path used for `target` during a transactional batch.
+///
+/// The suffix is appended rather than replacing the extension, so `a.txt`
+/// and `a.md` get distinct backups.
+pub fn backup_path_for(target: &Path) -> PathBuf {
+    let mut name = OsString::from(target.as_os_str());
+    name.push(".prepend_backup");
+    PathBuf::from(name)
+}
@@ -0,0 +1,279 @@
+//! Pure text transforms applied to the prepend text before it is written.
+//!
+//! These only ever touch the header, never the original file content.
+
+use crate::error::PrependError;
+use crate::plugin::{ContentTransform, FileContext};
+use std::str::FromStr;
+
+/// How much to indent each line of the prepend text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    /// Indent by a fixed number of spaces or tabs (see [`IndentStyle`])
+    Fixed(usize),
+    /// Reuse the leading whitespace of the target's first line
+    MatchFirstLine,
+}
+
+/// Character used for [`Indent::Fixed`] indentation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// Indent with spaces
+    #[default]
+    Spaces,
+    /// Indent with tabs
+    Tabs,
+}
+
+/// Tab stop width used by `--unexpand`, as in `unexpand(1)`
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Conversion between tabs and spaces (`--expand-tabs`, `--unexpand`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabConversion {
+    /// Replace every tab by spaces up to the next tab stop
+    Expand(usize),
+    /// Rewrite leading whitespace as tabs, padded with spaces
+    Unexpand(usize),
+}
+
+impl ContentTransform for TabConversion {
+    fn transform_header(&self, header: &[u8], _context: &FileContext) -> Vec<u8> {
+        let text = String::from_utf8_lossy(header);
+        let converted = match *self {
+            TabConversion::Expand(width) => expand_tabs(&text, width),
+            TabConversion::Unexpand(width) => unexpand_tabs(&text, width),
+        };
+        converted.into_bytes()
+    }
+}
+
+/// How line breaks at the end of the text and of the result are handled
+///
+/// | Policy | End of the text | End of the file |
+/// |--------|-----------------|-----------------|
+/// | `Preserve` | as given | as it was |
+/// | `Auto` | `\n` added if missing | as it was |
+/// | `Force` | `\n` added if missing | `\n` added if missing |
+/// | `None` | trailing line breaks removed | as it was |
+///
+/// With `None` the text runs straight into the line it is inserted before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NewlinePolicy {
+    /// Use the text byte-for-byte
+    #[default]
+    Preserve,
+    /// End the text with a newline so it can't merge into the next line
+    Auto,
+    /// Like `Auto`, and also end the resulting file with a newline
+    Force,
+    /// Strip line breaks from the end of the text
+    None,
+}
+
+impl NewlinePolicy {
+    /// Applies the policy to the end of `text`.
+    pub fn apply_to_text(self, text: &str) -> String {
+        match self {
+            NewlinePolicy::Preserve => text.to_string(),
+            NewlinePolicy::Auto | NewlinePolicy::Force if !text.ends_with('\n') => {
+                format!("{}\n", text)
+            }
+            NewlinePolicy::Auto | NewlinePolicy::Force => text.to_string(),
+            NewlinePolicy::None => text.trim_end_matches(['\n', '\r']).to_string(),
+        }
+    }
+}
+
+impl FromStr for NewlinePolicy {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "preserve" => Ok(NewlinePolicy::Preserve),
+            "auto" => Ok(NewlinePolicy::Auto),
+            "force" => Ok(NewlinePolicy::Force),
+            "none" => Ok(NewlinePolicy::None),
+            _ => Err(PrependError::InvalidValue(
+                "--newline".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+/// Line ending every line break is rewritten to (`--normalize-eol`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EolStyle {
+    /// Unix style `\n`
+    Lf,
+    /// Windows style `\r\n`
+    Crlf,
+}
+
+impl EolStyle {
+    /// Returns the line ending itself.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EolStyle::Lf => "\n",
+            EolStyle::Crlf => "\r\n",
+        }
+    }
+}
+
+impl FromStr for EolStyle {
+    type Err = PrependError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "lf" => Ok(EolStyle::Lf),
+            "crlf" => Ok(EolStyle::Crlf),
+            _ => Err(PrependError::InvalidValue(
+                "--normalize-eol".to_string(),
+                value.to_string(),
+            )),
+        }
+    }
+}
+
+/// Rewrites every `\n` and `\r\n` in `text` to `style`.
+///
+/// A `\r` that isn't followed by `\n` is not a line break and is kept as
+/// it is, so in `a\r\r\n` only the final `\r\n` is rewritten.
+pub fn normalize_line_endings(text: &str, style: EolStyle) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        match line.strip_suffix('\n') {
+            Some(content) => {
+                out.push_str(content.strip_suffix('\r').unwrap_or(content));
+                out.push_str(style.as_str());
+            }
+            None => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Builds the whitespace prefix for `width` levels in `style`.
+pub fn indent_prefix(width: usize, style: IndentStyle) -> String {
+    match style {
+        IndentStyle::Spaces => " ".repeat(width),
+        IndentStyle::Tabs => "\t".repeat(width),
+    }
+}
+
+/// Returns the leading spaces and tabs of `line`.
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line
+        .find(|c: char| c != ' ' && c != '\t')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Prefixes every non-blank line of `text` with `prefix`.
+///
+/// Blank (or whitespace-only) lines are left alone so the header doesn't
+/// gain trailing whitespace. Line endings are preserved.
+pub fn indent_lines(text: &str, prefix: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            out.push_str(prefix);
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// Rewrites `text` into a canonical form so regenerating a header yields
+/// byte-identical output.
+///
+/// Line endings become `\n`, trailing whitespace is trimmed from each line,
+/// the indentation shared by all non-blank lines is removed, leading and
+/// trailing blank lines are dropped and the result ends in exactly one
+/// newline. Text that is empty after this stays empty.
+///
+/// The transform is idempotent: canonicalizing its own output is a no-op.
+pub fn canonicalize(text: &str) -> String {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|line| line.trim_end_matches([' ', '\t', '\r']))
+        .collect();
+
+    let common = lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| leading_whitespace(line))
+        .reduce(common_prefix)
+        .unwrap_or_default()
+        .len();
+
+    let first = lines.iter().position(|line| !line.is_empty());
+    let last = lines.iter().rposition(|line| !line.is_empty());
+    let (Some(first), Some(last)) = (first, last) else {
+        return String::new();
+    };
+
+    let mut out = String::with_capacity(text.len());
+    for line in &lines[first..=last] {
+        if !line.is_empty() {
+            out.push_str(&line[common..]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Returns the longest common prefix of two whitespace runs.
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    &a[..len]
+}
+
+/// Replaces every tab in `text` with spaces up to the next multiple of
+/// `width` columns, so the text lines up exactly as it did with tabs.
+///
+/// Columns restart at each `\n`; every other character is one column wide.
+pub fn expand_tabs(text: &str, width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = width - column % width;
+                out.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\n' => {
+                out.push(c);
+                column = 0;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Rewrites the leading whitespace of each line of `text` as tabs of
+/// `width` columns followed by the remaining spaces.
+///
+/// Only indentation is converted, like `unexpand(1)` without `-a`, so
+/// alignment inside a line is left alone. Blank lines are kept as they are.
+pub fn unexpand_tabs(text: &str, width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let indent = leading_whitespace(line);
+        if indent.len() == line.trim_end_matches(['\r', '\n']).len() {
+            out.push_str(line);
+            continue;
+        }
+        let columns = expand_tabs(indent, width).len();
+        out.extend(std::iter::repeat_n('\t', columns / width));
+        out.extend(std::iter::repeat_n(' ', columns % width));
+        out.push_str(&line[indent.len()..]);
+    }
+    out
+}
@@ -0,0 +1,105 @@
+//! Structured warnings with stable, machine-readable codes.
+//!
+//! Warnings never stop an operation on their own. Each one carries a
+//! [`WarningCode`] that stays the same across releases, so scripts and JSON
+//! consumers can react to specific warnings without matching on text.
+
+use crate::error::PrependError;
+use crate::json::JsonObject;
+use std::fmt;
+
+/// Stable identifier for each kind of warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningCode {
+    /// W001: the file extension is not on the allowed list
+    UncommonExtension,
+    /// W002: the file was skipped because another process holds its lock
+    LockedFileSkipped,
+    /// W003: no line matched the insertion pattern, so the text was appended
+    PatternNotMatched,
+    /// W004: the prepend text contains a NUL byte
+    NulInText,
+    /// W005: `--fast-temp` could not use the system temp dir, so the copy was
+    /// written next to the target
+    FastTempUnavailable,
+    /// W006: `--normalize-eol` left a target that doesn't look like text
+    /// alone
+    EolNotNormalized,
+}
+
+impl WarningCode {
+    /// Returns the stable code, e.g. `W001`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WarningCode::UncommonExtension => "W001",
+            WarningCode::LockedFileSkipped => "W002",
+            WarningCode::PatternNotMatched => "W003",
+            WarningCode::NulInText => "W004",
+            WarningCode::FastTempUnavailable => "W005",
+            WarningCode::EolNotNormalized => "W006",
+        }
+    }
+}
+
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A non-fatal problem found while validating or processing a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Machine-readable code
+    pub code: WarningCode,
+    /// Human-readable explanation
+    pub message: String,
+}
+
+impl Warning {
+    /// Creates a warning with the given code and message.
+    pub fn new(code: WarningCode, message: impl Into<String>) -> Self {
+        Warning {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Renders the warning as a JSON object with `code` and `message` fields.
+    pub fn to_json(&self) -> String {
+        JsonObject::new()
+            .string("code", self.code.as_str())
+            .string("message", &self.message)
+            .render()
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.code, self.message)
+    }
+}
+
+/// How warnings are treated (`--strict`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Warnings are reported and never fail anything
+    #[default]
+    Off,
+    /// The first warning fails the target, before it is modified where possible
+    Fail,
+    /// Targets are processed normally, but the run fails at the end if any
+    /// warning was raised
+    FailAtEnd,
+}
+
+impl Strictness {
+    /// Turns the first of `warnings` into [`PrependError::WarningAsError`]
+    /// under [`Strictness::Fail`]; otherwise passes them through.
+    pub fn enforce(self, mut warnings: Vec<Warning>) -> Result<Vec<Warning>, PrependError> {
+        if self == Strictness::Fail && !warnings.is_empty() {
+            return Err(PrependError::WarningAsError(warnings.remove(0)));
+        }
+        Ok(warnings)
+    }
+}
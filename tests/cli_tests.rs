@@ -1,3 +1,6 @@
+// The baseline cmd() helper converts the macro's Command explicitly.
+#![allow(clippy::useless_conversion)]
+
 use assert_cmd::Command; // Fixes the mismatched type error by using the correct struct
 use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
@@ -9,7 +12,10 @@ fn cmd() -> Command {
     // This is the correct, non-deprecated way to instantiate the command,
     // converting the std::process::Command returned by the macro into
     // the assert_cmd::Command struct required for all test methods.
-    Command::from(cargo_bin_cmd!("prepend"))
+    let mut cmd = Command::from(cargo_bin_cmd!("prepend"));
+    // Keep the caller's environment from leaking a default header into tests
+    cmd.env_remove("PREPEND_TEXT");
+    cmd
 }
 
 // --- End-to-End Tests ---
@@ -45,9 +51,11 @@ fn test_cli_dry_run_mode() {
     let mut cmd = cmd();
     cmd.arg("--dry-run").arg(file.path()).arg("Header text");
 
+    // Exit status 1: the file would change
     cmd.assert()
-        .success()
+        .code(1)
         .stdout(predicate::str::contains("\x1b[33mDRY-RUN MODE:\x1b[0m"))
+        .stdout(predicate::str::contains("atomic: true"))
         .stdout(predicate::str::contains("Header text"));
 
     // Crucial assertion: file must not be modified
@@ -97,6 +105,27 @@ fn test_cli_directory_instead_of_file() {
         .stderr(predicate::str::contains("not a regular file"));
 }
 
+#[cfg(unix)]
+#[test]
+fn test_cli_file_not_writable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("locked.txt");
+    fs::write(&file_path, "content").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o444)).unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg(&file_path).arg("Header");
+
+    // The directory is writable, so only the check stops the file being replaced
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("not writable"));
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "content");
+}
+
 #[test]
 fn test_cli_interactive_mode_with_stdin() {
     let file = NamedTempFile::new().unwrap();
@@ -136,16 +165,53 @@ fn test_cli_empty_stdin() {
     let file = NamedTempFile::new().unwrap();
     fs::write(file.path(), "Original\n").unwrap();
 
+    // stdin is an empty pipe, not a terminal: no prompt, just a clear error
     let mut cmd = cmd();
     cmd.arg(file.path()).write_stdin("");
 
     cmd.assert()
         .failure()
         .code(1)
+        .stdout(predicate::str::contains("Ready to process").not())
+        .stdout(predicate::str::contains("Enter text").not())
         .stderr(predicate::str::contains("ERROR"))
+        .stderr(predicate::str::contains(
+            "No text provided and stdin is not a terminal.",
+        ));
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "Original\n");
+}
+
+#[test]
+fn test_cli_empty_text_stdin() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    // Text explicitly requested from stdin keeps the plain message
+    let mut cmd = cmd();
+    cmd.arg("--text-stdin").arg(file.path()).write_stdin("");
+
+    cmd.assert()
+        .failure()
         .stderr(predicate::str::contains("Input text is empty."));
 }
 
+#[test]
+fn test_cli_piped_text_without_prompt() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg(file.path()).write_stdin("Piped");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Ready to process").not());
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        "Piped\nOriginal\n"
+    );
+}
+
 #[test]
 fn test_cli_uncommon_extension_warning() {
     let dir = TempDir::new().unwrap();
@@ -198,3 +264,1415 @@ fn test_cli_special_characters() {
     // The program adds a newline after the argument
     assert!(content.starts_with(&format!("{}\n", special_text)));
 }
+
+#[test]
+fn test_cli_expands_glob_pattern() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.txt"), "A\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "B\n").unwrap();
+    fs::write(dir.path().join("c.log"), "C\n").unwrap();
+
+    // Passed as a single literal argument, like cmd.exe or PowerShell would
+    let pattern = dir.path().join("*.txt");
+    let mut cmd = cmd();
+    cmd.arg(&pattern).arg("Header");
+
+    cmd.assert().success();
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "Header\nA\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+        "Header\nB\n"
+    );
+    assert_eq!(fs::read_to_string(dir.path().join("c.log")).unwrap(), "C\n");
+}
+
+#[test]
+fn test_cli_no_glob_keeps_pattern_literal() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.txt"), "A\n").unwrap();
+
+    let pattern = dir.path().join("*.txt");
+    let mut cmd = cmd();
+    cmd.arg("--no-glob").arg(&pattern).arg("Header");
+
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("does not exist"));
+
+    assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "A\n");
+}
+
+#[test]
+fn test_cli_same_file_twice_prepended_once() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg(file.path()).arg(file.path()).arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("SKIPPED"));
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "Header\nOriginal\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_cli_emit_patch_applies_cleanly() {
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("file.txt");
+    let original = "Line 1\nLine 2\nLine 3\nLine 4\n";
+    fs::write(&target, original).unwrap();
+    let patch_path = dir.path().join("out.patch");
+
+    let mut cmd = cmd();
+    cmd.arg("--emit-patch")
+        .arg(&patch_path)
+        .arg(&target)
+        .arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Patch written"));
+
+    // The target itself must not be modified
+    assert_eq!(fs::read_to_string(&target).unwrap(), original);
+
+    let patch = fs::read_to_string(&patch_path).unwrap();
+    assert!(patch.contains("@@ -1,3 +1,4 @@\n+Header\n Line 1\n"));
+
+    let status = std::process::Command::new("patch")
+        .arg("--quiet")
+        .arg(&target)
+        .arg(&patch_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        format!("Header\n{}", original)
+    );
+}
+
+#[test]
+fn test_cli_emit_patch_leaves_out_skipped_targets() {
+    let dir = TempDir::new().unwrap();
+    let present = dir.path().join("present.txt");
+    let empty = dir.path().join("empty.txt");
+    let fresh = dir.path().join("fresh.txt");
+    fs::write(&present, "Header\nbody\n").unwrap();
+    fs::write(&empty, "").unwrap();
+    fs::write(&fresh, "body\n").unwrap();
+    let patch_path = dir.path().join("out.patch");
+
+    cmd()
+        .arg("--emit-patch")
+        .arg(&patch_path)
+        .arg("--skip-if-present")
+        .arg("--skip-empty")
+        .arg(&present)
+        .arg(&empty)
+        .arg(&fresh)
+        .arg("Header")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("already starts with the text"))
+        .stdout(predicate::str::contains("is empty"));
+
+    let patch = fs::read_to_string(&patch_path).unwrap();
+    assert!(patch.contains("fresh.txt"));
+    assert!(!patch.contains("present.txt"));
+    assert!(!patch.contains("empty.txt"));
+}
+
+#[test]
+fn test_cli_emit_patch_rejects_unmodeled_options() {
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("file.txt");
+    fs::write(&target, "one\ntwo").unwrap();
+    let patch_path = dir.path().join("out.patch");
+
+    for extra in [
+        &["--newline", "force"][..],
+        &["--limit-lines", "1", "--allow-truncate"],
+    ] {
+        cmd()
+            .arg("--emit-patch")
+            .arg(&patch_path)
+            .args(extra)
+            .arg(&target)
+            .arg("Header")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--emit-patch"));
+    }
+    assert!(!patch_path.exists());
+}
+
+#[test]
+fn test_cli_invalid_utf8_stdin_fails_by_default() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg(file.path())
+        .write_stdin(b"bad \xff byte\n".to_vec());
+
+    cmd.assert().failure().code(1);
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "Original\n");
+}
+
+#[test]
+fn test_cli_lossy_replaces_invalid_utf8() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--lossy")
+        .arg(file.path())
+        .write_stdin(b"bad \xff byte\n".to_vec());
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("SUCCESS"));
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "bad \u{FFFD} byte\nOriginal\n");
+}
+
+#[test]
+fn test_cli_env_var_default_text() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    // No text argument and stdin is not a terminal
+    let mut cmd = cmd();
+    cmd.env("PREPEND_TEXT", "Env Header").arg(file.path());
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("SUCCESS"));
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "Env Header\nOriginal\n");
+}
+
+#[test]
+fn test_cli_argument_overrides_env_var() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.env("PREPEND_TEXT", "Env Header")
+        .arg(file.path())
+        .arg("Arg Header");
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "Arg Header\nOriginal\n");
+}
+
+#[test]
+fn test_cli_doctor_text_report() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Header\r\nbody\r\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("doctor").arg(file.path()).arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Prepend Doctor:"))
+        .stdout(predicate::str::contains("crlf"))
+        .stdout(predicate::str::contains("Ready for prepending."));
+
+    // Doctor must never modify the target
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        "Header\r\nbody\r\n"
+    );
+}
+
+#[test]
+fn test_cli_doctor_json_report() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Header\nbody\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("doctor")
+        .arg("--format")
+        .arg("json")
+        .arg(file.path())
+        .arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("\"encoding\":\"utf-8\""))
+        .stdout(predicate::str::contains("\"line_ending\":\"lf\""))
+        .stdout(predicate::str::contains("\"header_present\":true"))
+        .stdout(predicate::str::contains(
+            "\"size\":12,\"estimated_size\":19",
+        ));
+}
+
+#[test]
+fn test_cli_doctor_missing_file() {
+    let mut cmd = cmd();
+    cmd.arg("doctor").arg("/nonexistent/file.txt");
+
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains("missing"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_doctor_agrees_with_a_real_run() {
+    let dir = TempDir::new().unwrap();
+    let fifo = dir.path().join("pipe.txt");
+    let status = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Refused for the same reason a prepend would give
+    cmd()
+        .arg("doctor")
+        .arg(&fifo)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("named pipe (FIFO)"));
+    cmd()
+        .arg(&fifo)
+        .arg("Header")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("named pipe (FIFO)"));
+
+    // The header is terminated once, as in argument mode
+    let file = dir.path().join("notes.txt");
+    fs::write(&file, "body\n").unwrap();
+    cmd()
+        .arg("doctor")
+        .arg("--format")
+        .arg("json")
+        .arg(&file)
+        .arg("Header\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"estimated_size\":12"))
+        .stdout(predicate::str::contains("\"problem\":null"));
+}
+
+#[test]
+fn test_cli_file_named_doctor() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("doctor");
+    fs::write(&file, "body\n").unwrap();
+
+    for args in [&["./doctor", "A"][..], &["--", "doctor", "B"]] {
+        cmd()
+            .current_dir(dir.path())
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("SUCCESS"));
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), "B\nA\nbody\n");
+}
+
+#[test]
+fn test_cli_raw_writes_exact_bytes() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--raw").arg(file.path()).arg("Header ");
+
+    cmd.assert().success();
+
+    let content = fs::read(file.path()).unwrap();
+    assert_eq!(content, b"Header Original\n");
+}
+
+#[test]
+fn test_cli_raw_keeps_multiple_trailing_newlines() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--raw").arg(file.path()).arg("Header\r\n\n");
+
+    cmd.assert().success();
+
+    let content = fs::read(file.path()).unwrap();
+    assert_eq!(content, b"Header\r\n\nOriginal\n");
+}
+
+#[test]
+fn test_cli_raw_with_strict_newline_fails() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--raw")
+        .arg("--strict-newline")
+        .arg(file.path())
+        .arg("Header");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("does not end with a newline"));
+
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "Original\n");
+}
+
+#[test]
+fn test_cli_normalize_eol() {
+    let dir = TempDir::new().unwrap();
+    let text = dir.path().join("mixed.txt");
+    let binary = dir.path().join("data.txt");
+    fs::write(&text, "one\r\ntwo\n").unwrap();
+    fs::write(&binary, b"\x00\r\n").unwrap();
+
+    cmd()
+        .arg("--normalize-eol")
+        .arg("lf")
+        .arg(&text)
+        .arg(&binary)
+        .arg("Header\r\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[W006]"));
+
+    assert_eq!(fs::read_to_string(&text).unwrap(), "Header\none\ntwo\n");
+    assert_eq!(fs::read(&binary).unwrap(), b"Header\r\n\x00\r\n");
+
+    cmd()
+        .arg("--normalize-eol")
+        .arg("cr")
+        .arg(&text)
+        .arg("Header")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--normalize-eol"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_create_with_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("run.sh");
+
+    cmd()
+        .arg("--create")
+        .arg("--mode")
+        .arg("755")
+        .arg(&script)
+        .arg("#!/bin/sh")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&script).unwrap(), "#!/bin/sh\n");
+    let mode = fs::metadata(&script).unwrap().permissions().mode();
+    assert_eq!(mode & 0o7777, 0o755);
+
+    // Existing files keep the usual permissions unless --force is given
+    cmd()
+        .arg("--mode")
+        .arg("700")
+        .arg(&script)
+        .arg("# one")
+        .assert()
+        .success();
+    let mode = fs::metadata(&script).unwrap().permissions().mode();
+    assert_ne!(mode & 0o7777, 0o700);
+
+    cmd()
+        .arg("--mode")
+        .arg("700")
+        .arg("--force")
+        .arg(&script)
+        .arg("# two")
+        .assert()
+        .success();
+    let mode = fs::metadata(&script).unwrap().permissions().mode();
+    assert_eq!(mode & 0o7777, 0o700);
+}
+
+#[test]
+fn test_cli_create_leaves_nothing_on_failure() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("new.txt");
+
+    // The NUL byte fails under --strict only after the target was created
+    let mut cmd = cmd();
+    cmd.arg("--create")
+        .arg("--strict")
+        .arg("--text-stdin")
+        .arg(&path)
+        .write_stdin("a\0b");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("W004"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_cli_mode_rejects_invalid_octal() {
+    let dir = TempDir::new().unwrap();
+    let script = dir.path().join("run.sh");
+
+    cmd()
+        .arg("--create")
+        .arg("--mode")
+        .arg("0o758")
+        .arg(&script)
+        .arg("#!/bin/sh")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid value '0o758' for option --mode",
+        ));
+
+    assert!(!script.exists());
+}
+
+#[test]
+fn test_cli_hash_marker() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "echo hi\n").unwrap();
+    let run = |text: &str| {
+        cmd()
+            .arg("--hash-marker")
+            .arg("#")
+            .arg(file.path())
+            .arg(text)
+            .assert()
+            .success()
+    };
+
+    run("# Copyright 2025");
+    let first = fs::read_to_string(file.path()).unwrap();
+    assert!(first.starts_with("# Copyright 2025\n# prepend-hash: "));
+
+    run("# Copyright 2025").stdout(predicate::str::contains("hash matches"));
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), first);
+
+    run("# Copyright 2026");
+    let second = fs::read_to_string(file.path()).unwrap();
+    let lines: Vec<&str> = second.lines().collect();
+    assert_eq!(lines[0], "# Copyright 2026");
+    assert_ne!(lines[1], first.lines().nth(1).unwrap());
+    assert_eq!(&lines[2..], ["echo hi"]);
+}
+
+#[test]
+fn test_cli_limit_lines_requires_output() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "1\n2\n3\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--limit-lines")
+        .arg("1")
+        .arg(file.path())
+        .arg("Header");
+
+    cmd.assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("--allow-truncate"));
+
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "1\n2\n3\n");
+}
+
+#[test]
+fn test_cli_limit_lines_with_allow_truncate() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "1\n2\n3\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--limit-lines")
+        .arg("1")
+        .arg("--allow-truncate")
+        .arg(file.path())
+        .arg("Header");
+
+    cmd.assert().success();
+
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "Header\n1\n");
+}
+
+#[test]
+fn test_cli_json_output_carries_warning_code() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.xyz");
+    fs::write(&file_path, "content\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--format")
+        .arg("json")
+        .arg(&file_path)
+        .arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("\"status\":\"success\""))
+        .stdout(predicate::str::contains("\"code\":\"W001\""))
+        .stdout(predicate::str::contains("WARNING").not());
+}
+
+#[test]
+fn test_cli_text_warning_includes_code() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.xyz");
+    fs::write(&file_path, "content\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg(&file_path).arg("Header");
+
+    cmd.assert().success().stdout(predicate::str::contains(
+        "[W001] Uncommon extension '.xyz'.",
+    ));
+}
+
+#[test]
+fn test_cli_before_pattern() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "#!/bin/sh\nset -e\necho hi\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--before-pattern")
+        .arg("^echo")
+        .arg(file.path())
+        .arg("# setup done");
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "#!/bin/sh\nset -e\n# setup done\necho hi\n");
+}
+
+#[test]
+fn test_cli_dry_run_exit_status_reports_changes() {
+    let dir = TempDir::new().unwrap();
+    let stamped = dir.path().join("stamped.txt");
+    let fresh = dir.path().join("fresh.txt");
+    fs::write(&stamped, "Header\nbody\n").unwrap();
+    fs::write(&fresh, "body\n").unwrap();
+
+    // Already up to date: nothing would change
+    let mut current = cmd();
+    current
+        .arg("--dry-run")
+        .arg("--skip-if-present")
+        .arg(&stamped)
+        .arg("Header");
+    current.assert().code(0);
+
+    // One file out of date is enough for a non-zero status
+    let mut stale = cmd();
+    stale
+        .arg("--dry-run")
+        .arg("--skip-if-present")
+        .arg(&stamped)
+        .arg(&fresh)
+        .arg("Header");
+    stale.assert().code(1);
+
+    assert_eq!(fs::read_to_string(&stamped).unwrap(), "Header\nbody\n");
+    assert_eq!(fs::read_to_string(&fresh).unwrap(), "body\n");
+}
+
+#[test]
+fn test_cli_dry_run_marks_insertion_boundary() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "#!/bin/sh\nset -e\necho hi\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--dry-run")
+        .arg("--before-pattern")
+        .arg("^echo")
+        .arg(file.path())
+        .arg("# setup done");
+
+    cmd.assert().code(1).stdout(predicate::str::contains(
+        "set -e\n\x1b[32m>>> inserted\x1b[0m\n\x1b[32m# setup done\x1b[0m\n\x1b[32m<<<\x1b[0m\necho hi\n",
+    ));
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        "#!/bin/sh\nset -e\necho hi\n"
+    );
+}
+
+#[test]
+fn test_cli_before_pattern_no_match_warns() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "body\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--before-pattern")
+        .arg("^missing")
+        .arg(file.path())
+        .arg("tail");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[W003]"));
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "body\ntail\n");
+}
+
+#[test]
+fn test_cli_after_pattern() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "package main\n\nfunc main() {}\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--after-pattern")
+        .arg("^package ")
+        .arg(file.path())
+        .arg("// Code generated. DO NOT EDIT.");
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(
+        content,
+        "package main\n// Code generated. DO NOT EDIT.\n\nfunc main() {}\n"
+    );
+}
+
+#[test]
+fn test_cli_time_json_reports_duration() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--time")
+        .arg("--format")
+        .arg("json")
+        .arg(file.path())
+        .arg("Header");
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).unwrap();
+
+    let duration = stdout
+        .split("\"duration_ms\":")
+        .nth(1)
+        .and_then(|rest| rest.split([',', '}']).next())
+        .and_then(|value| value.parse::<f64>().ok())
+        .expect("duration_ms field");
+    assert!((0.0..60_000.0).contains(&duration));
+    assert!(stdout.contains("\"throughput_mbps\":"));
+}
+
+#[test]
+fn test_cli_fail_fast_stops_batch() {
+    let dir = TempDir::new().unwrap();
+    let first = dir.path().join("first.txt");
+    let last = dir.path().join("last.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&last, "three\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--fail-fast")
+        .arg(&first)
+        .arg(dir.path().join("missing.txt"))
+        .arg(&last)
+        .arg("H");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("ABORTED:"));
+
+    assert_eq!(fs::read_to_string(&first).unwrap(), "H\none\n");
+    assert_eq!(fs::read_to_string(&last).unwrap(), "three\n");
+}
+
+#[test]
+fn test_cli_text_stdin_skips_prompt() {
+    let dir = TempDir::new().unwrap();
+    let first = dir.path().join("a.txt");
+    let second = dir.path().join("b.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&second, "two\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--text-stdin")
+        .arg(&first)
+        .arg(&second)
+        .write_stdin("Piped header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Ready to process").not())
+        .stdout(predicate::str::contains("Enter text").not());
+
+    assert_eq!(fs::read_to_string(&first).unwrap(), "Piped header\none\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "Piped header\ntwo\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_text_command() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--text-command")
+        .arg("echo generated")
+        .arg(file.path());
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "generated\nOriginal\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_text_command_failure() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Original\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--text-command")
+        .arg("echo partial; exit 3")
+        .arg(file.path());
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("failed"));
+
+    let content = fs::read_to_string(file.path()).unwrap();
+    assert_eq!(content, "Original\n");
+}
+
+#[test]
+fn test_cli_require_extension() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("data.xyz");
+    fs::write(&path, "content\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--require-extension").arg(&path).arg("Header");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "does not have an allowed extension",
+    ));
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "content\n");
+}
+
+#[test]
+fn test_cli_strict_modes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("data.xyz");
+    fs::write(&path, "content\n").unwrap();
+
+    let mut strict = cmd();
+    strict.arg("--strict").arg(&path).arg("Header");
+    strict.assert().failure().stderr(predicate::str::contains(
+        "--strict treats warnings as errors",
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "content\n");
+
+    let mut at_end = cmd();
+    at_end.arg("--strict-at-end").arg(&path).arg("Header");
+    at_end
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--strict-at-end is set"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "Header\ncontent\n");
+}
+
+#[test]
+fn test_cli_max_files_needs_yes_on_a_pipe() {
+    let dir = TempDir::new().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&second, "two\n").unwrap();
+
+    // assert_cmd gives the child a pipe for stdin, so no prompt can be answered
+    let mut refused = cmd();
+    refused
+        .arg("--max-files")
+        .arg("1")
+        .arg(&first)
+        .arg(&second)
+        .arg("H");
+    refused
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--yes proceeds anyway"));
+    assert_eq!(fs::read_to_string(&first).unwrap(), "one\n");
+
+    let mut confirmed = cmd();
+    confirmed
+        .arg("-y")
+        .arg("--max-files")
+        .arg("1")
+        .arg(&first)
+        .arg(&second)
+        .arg("H");
+    confirmed.assert().success();
+    assert_eq!(fs::read_to_string(&first).unwrap(), "H\none\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "H\ntwo\n");
+}
+
+#[test]
+fn test_cli_print_temp_path() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "body\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--print-temp-path").arg(&path).arg("Header");
+
+    let expected = format!("TEMP PATH: {:?}", dir.path().join("notes.tmp_prepend"));
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(expected));
+}
+
+#[test]
+fn test_cli_only_added() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "original body\n").unwrap();
+
+    let mut text = cmd();
+    text.arg("--only-added")
+        .arg("--indent")
+        .arg("2")
+        .arg(&path)
+        .arg("one\ntwo");
+    text.assert()
+        .code(1)
+        .stdout(predicate::str::contains(format!(
+            "==> {} <==\n  one\n  two\n",
+            path.display()
+        )))
+        .stdout(predicate::str::contains("original body").not());
+
+    let mut json = cmd();
+    json.arg("--only-added")
+        .arg("--format")
+        .arg("json")
+        .arg(&path)
+        .arg("one");
+    json.assert()
+        .code(1)
+        .stdout(predicate::str::contains(r#""added_lines":["one"]"#));
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "original body\n");
+}
+
+#[test]
+fn test_cli_only_added_with_new_section() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.ini");
+    fs::write(&path, "[a]\nx = 1\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--only-added")
+        .arg("--under-section")
+        .arg("b")
+        .arg(&path)
+        .arg("y = 2");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains(format!(
+            "==> {} <==\n[b]\ny = 2\n",
+            path.display()
+        )));
+}
+
+#[test]
+fn test_cli_skip_if_present() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "Header\nbody\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--skip-if-present").arg(file.path()).arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("already starts with the text"));
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "Header\nbody\n");
+}
+
+#[test]
+fn test_cli_skip_empty() {
+    let dir = TempDir::new().unwrap();
+    let empty = dir.path().join("placeholder.txt");
+    let full = dir.path().join("notes.txt");
+    fs::write(&empty, "").unwrap();
+    fs::write(&full, "body\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--skip-empty").arg(&empty).arg(&full).arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("is empty"));
+    assert_eq!(fs::read_to_string(&empty).unwrap(), "");
+    assert_eq!(fs::read_to_string(&full).unwrap(), "Header\nbody\n");
+}
+
+#[test]
+fn test_cli_write_manifest() {
+    let dir = TempDir::new().unwrap();
+    let good = dir.path().join("good.txt");
+    let missing = dir.path().join("missing.txt");
+    let csv = dir.path().join("manifest.csv");
+    let json = dir.path().join("manifest.json");
+    fs::write(&good, "one\n").unwrap();
+
+    let mut first = cmd();
+    first
+        .arg("--write-manifest")
+        .arg(&csv)
+        .arg("--manifest-format")
+        .arg("csv")
+        .arg(&good)
+        .arg(&missing)
+        .arg("H");
+    first.assert().failure();
+
+    // Written despite the failure, with one row per target
+    let manifest = fs::read_to_string(&csv).unwrap();
+    let rows: Vec<&str> = manifest.lines().collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows[0],
+        "file,status,old_size,new_size,bytes_added,backup,error"
+    );
+    assert_eq!(rows[1], format!("{},success,4,6,2,,", good.display()));
+    assert!(rows[2].starts_with(&format!("{},error,,,,,", missing.display())));
+
+    let mut second = cmd();
+    second
+        .arg("--write-manifest")
+        .arg(&json)
+        .arg("--fail-fast")
+        .arg(&missing)
+        .arg(&good)
+        .arg("H");
+    second.assert().failure();
+
+    let manifest = fs::read_to_string(&json).unwrap();
+    assert!(manifest.starts_with("{\"schema_version\":1,\"files\":["));
+    assert!(manifest.contains("\"status\":\"error\",\"error\":"));
+    assert!(manifest.contains("\"status\":\"not_processed\",\"old_size\":6}"));
+}
+
+#[test]
+fn test_cli_fast_temp() {
+    let dir = TempDir::new().unwrap();
+    let scratch = TempDir::new().unwrap();
+    let file = dir.path().join("big.log");
+    fs::write(&file, "line\n").unwrap();
+
+    let mut fast = cmd();
+    fast.env("TMPDIR", scratch.path())
+        .arg("--fast-temp")
+        .arg(&file)
+        .arg("H");
+    fast.assert()
+        .success()
+        .stdout(predicate::str::contains("W005").not());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "H\nline\n");
+    // The scratch copy is moved out of the system temp dir
+    assert_eq!(fs::read_dir(scratch.path()).unwrap().count(), 0);
+
+    // An unusable temp dir falls back to the usual temp file, with a warning
+    let mut fallback = cmd();
+    fallback
+        .env("TMPDIR", dir.path().join("missing"))
+        .arg("--fast-temp")
+        .arg(&file)
+        .arg("G");
+    fallback
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[W005]"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "G\nH\nline\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_status_fd_reports_events() {
+    let dir = TempDir::new().unwrap();
+    let good = dir.path().join("good.txt");
+    let missing = dir.path().join("missing.txt");
+    fs::write(&good, "one\n").unwrap();
+
+    // fd 3 becomes the pipe assert_cmd reads as stdout; regular output is discarded
+    let mut status = Command::new("sh");
+    status
+        .arg("-c")
+        .arg("exec 3>&1 >/dev/null; \"$0\" --status-fd 3 \"$1\" \"$2\" H")
+        .arg(assert_cmd::cargo::cargo_bin!("prepend"))
+        .arg(&good)
+        .arg(&missing)
+        .env_remove("PREPEND_TEXT");
+    let output = status.assert().failure().get_output().stdout.clone();
+    let events: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+
+    assert_eq!(events.len(), 4);
+    assert_eq!(
+        events[0],
+        format!(
+            "{{\"schema_version\":1,\"event\":\"started\",\"file\":\"{}\"}}",
+            good.display()
+        )
+    );
+    assert!(events[1].contains("\"event\":\"finished\""));
+    assert!(events[1].contains("\"status\":\"success\",\"bytes_added\":2"));
+    assert!(events[2].contains("\"event\":\"started\""));
+    assert!(events[3].contains("\"event\":\"failed\""));
+    assert!(events[3].contains("does not exist"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_status_fd_pairs_skipped_targets() {
+    let dir = TempDir::new().unwrap();
+    let good = dir.path().join("good.txt");
+    fs::write(&good, "one\n").unwrap();
+
+    // The second copy is dropped as a duplicate before processing starts
+    let mut status = Command::new("sh");
+    status
+        .arg("-c")
+        .arg("exec 3>&1 >/dev/null; \"$0\" --status-fd 3 \"$1\" \"$1\" H")
+        .arg(assert_cmd::cargo::cargo_bin!("prepend"))
+        .arg(&good)
+        .env_remove("PREPEND_TEXT");
+    let output = status.assert().success().get_output().stdout.clone();
+    let events: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+
+    assert_eq!(events.len(), 4);
+    for pair in events.chunks(2) {
+        assert!(pair[0].contains("\"event\":\"started\""), "{:?}", pair);
+        assert!(pair[1].contains("\"event\":\"finished\""), "{:?}", pair);
+    }
+    assert!(events[1].contains("\"status\":\"skipped\""));
+}
+
+#[test]
+fn test_cli_status_fd_must_be_open() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "one\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--status-fd").arg("97").arg(file.path()).arg("H");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--status-fd 97"));
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), "one\n");
+}
+
+#[test]
+fn test_cli_transactional_rolls_back() {
+    let dir = TempDir::new().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&second, "two\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--transactional")
+        .arg(&first)
+        .arg(&second)
+        .arg(dir.path().join("missing.txt"))
+        .arg("H");
+
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("ROLLED BACK:"));
+
+    assert_eq!(fs::read_to_string(&first).unwrap(), "one\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "two\n");
+}
+
+#[test]
+fn test_cli_transactional_restores_only_modified() {
+    let dir = TempDir::new().unwrap();
+    let current = dir.path().join("current.txt");
+    let stale = dir.path().join("stale.txt");
+    fs::write(&current, "H\none\n").unwrap();
+    fs::write(&stale, "two\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--transactional")
+        .arg("--skip-if-present")
+        .arg(&current)
+        .arg(&stale)
+        .arg(dir.path().join("missing.txt"))
+        .arg("H");
+
+    // The skipped target was never rewritten, so there is nothing to restore
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "ROLLED BACK:\x1b[0m 1 modified file(s)",
+    ));
+
+    assert_eq!(fs::read_to_string(&current).unwrap(), "H\none\n");
+    assert_eq!(fs::read_to_string(&stale).unwrap(), "two\n");
+}
+
+#[test]
+fn test_cli_every_json_object_has_schema_version() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "one\n").unwrap();
+    fs::write(&b, "two\n").unwrap();
+    let missing = dir.path().join("missing.txt");
+
+    let runs: Vec<Vec<std::ffi::OsString>> = vec![
+        // success, duplicate skip, error, and the batch summary
+        vec![
+            "--time".into(),
+            a.clone().into(),
+            a.clone().into(),
+            b.clone().into(),
+            missing.clone().into(),
+            "H".into(),
+        ],
+        vec!["--dry-run".into(), a.clone().into(), "H".into()],
+        vec![
+            "--transactional".into(),
+            a.clone().into(),
+            missing.clone().into(),
+            "H".into(),
+        ],
+        vec!["doctor".into(), a.clone().into()],
+    ];
+
+    for args in runs {
+        let mut cmd = cmd();
+        if args[0] == "doctor" {
+            cmd.arg("doctor")
+                .arg("--format")
+                .arg("json")
+                .args(&args[1..]);
+        } else {
+            cmd.arg("--format").arg("json").args(&args);
+        }
+        let output = cmd.output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(!stdout.trim().is_empty());
+        for line in stdout.lines() {
+            assert!(
+                line.starts_with("{\"schema_version\":1,"),
+                "missing schema_version: {}",
+                line
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cli_recount_lines() {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), "one\ntwo\nthree").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--recount-lines")
+        .arg("--format")
+        .arg("json")
+        .arg(file.path())
+        .arg("Header");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("\"lines\":4"));
+}
+
+#[test]
+fn test_cli_spec_per_file_text() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "one\n").unwrap();
+    fs::write(&b, "two\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--spec")
+        .arg(format!("file={};text=A", a.display()))
+        .arg("--spec")
+        .arg(format!("file={};text=B\\;C", b.display()));
+
+    cmd.assert().success();
+
+    assert_eq!(fs::read_to_string(&a).unwrap(), "A\none\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "B;C\ntwo\n");
+}
+
+// Relies on the crash hook, which only exists in debug builds
+#[cfg(debug_assertions)]
+#[test]
+fn test_cli_crash_before_rename_leaves_original_intact() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("important.txt");
+    let original = "line\n".repeat(10_000);
+    fs::write(&path, &original).unwrap();
+
+    let mut cmd = cmd();
+    cmd.env("PREPEND_TEST_CRASH_BEFORE_RENAME", "1")
+        .arg(&path)
+        .arg("Header");
+
+    let status = cmd.output().unwrap().status;
+    assert!(!status.success());
+
+    // The original is untouched; only the fully written temp file is left over
+    assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    let leftover = fs::read_to_string(path.with_extension("tmp_prepend")).unwrap();
+    assert_eq!(leftover, format!("Header\n{}", original));
+}
+
+#[test]
+fn test_cli_modified_since() {
+    let dir = TempDir::new().unwrap();
+    let old = dir.path().join("old.txt");
+    let new = dir.path().join("new.txt");
+    fs::write(&old, "old\n").unwrap();
+    fs::write(&new, "new\n").unwrap();
+    let y2k = std::time::UNIX_EPOCH + std::time::Duration::from_secs(946_684_800);
+    fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(y2k)
+        .unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--modified-since")
+        .arg("2010-01-01T00:00:00Z")
+        .arg(&old)
+        .arg(&new)
+        .arg("H");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("not modified since"));
+
+    assert_eq!(fs::read_to_string(&old).unwrap(), "old\n");
+    assert_eq!(fs::read_to_string(&new).unwrap(), "H\nnew\n");
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+#[test]
+fn test_cli_git_tracked() {
+    let dir = TempDir::new().unwrap();
+    let tracked = dir.path().join("tracked.txt");
+    let untracked = dir.path().join("untracked.txt");
+    fs::write(&tracked, "tracked\n").unwrap();
+    fs::write(&untracked, "untracked\n").unwrap();
+    git(dir.path(), &["init", "-q"]);
+    git(dir.path(), &["add", "tracked.txt"]);
+    git(
+        dir.path(),
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ],
+    );
+
+    cmd()
+        .arg("--git-tracked")
+        .arg(&tracked)
+        .arg(&untracked)
+        .arg("H")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("is not tracked by git"));
+
+    assert_eq!(fs::read_to_string(&tracked).unwrap(), "H\ntracked\n");
+    assert_eq!(fs::read_to_string(&untracked).unwrap(), "untracked\n");
+}
+
+#[test]
+fn test_cli_git_tracked_outside_repository() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("file.txt");
+    fs::write(&file, "content\n").unwrap();
+
+    cmd()
+        .env("GIT_CEILING_DIRECTORIES", dir.path().parent().unwrap())
+        .arg("--git-tracked")
+        .arg(&file)
+        .arg("H")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not inside a git repository"));
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "content\n");
+}
+
+#[test]
+fn test_cli_pairs_from_stdin() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "one\n").unwrap();
+    fs::write(&b, "two\n").unwrap();
+
+    let mut cmd = cmd();
+    cmd.arg("--pairs").write_stdin(format!(
+        "{}\tHeaderA\n{}\tHeaderB\n",
+        a.display(),
+        b.display()
+    ));
+
+    cmd.assert().success();
+
+    assert_eq!(fs::read_to_string(&a).unwrap(), "HeaderA\none\n");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "HeaderB\ntwo\n");
+}
@@ -1,10 +1,40 @@
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 use tempfile::NamedTempFile;
 
-use prepend::constants::ALLOWED_EXTENSIONS;
-use prepend::{Config, perform_prepend, validate_file};
+use prepend::constants::{ALLOWED_EXTENSIONS, BUFFER_SIZE};
+use prepend::detect::{
+    Encoding, LineEnding, detect_encoding, detect_line_ending, dominant_line_ending, inspect,
+};
+use prepend::error::PrependError;
+use prepend::insert::{InsertPosition, NoMatch};
+use prepend::interactive::InteractiveReader;
+use prepend::lock::LockMode;
+use prepend::managed::{ManagedBlock, find_block, header_hash, mark};
+use prepend::manifest::{ManifestEntry, ManifestFormat, render_manifest};
+use prepend::patch::unified_diff;
+use prepend::plugin::{ContentTransform, FileContext};
+use prepend::present::{PresentMatch, needs_prepend};
+use prepend::replace::Replacement;
+use prepend::resume::{Checkpoint, checkpoint_path};
+use prepend::since::parse_cutoff;
+use prepend::space::{available_space, check_space};
+use prepend::spec::{Spec, parse_pairs};
+use prepend::template::TemplateValues;
+use prepend::transaction::backup_path_for;
+use prepend::transform::{
+    EolStyle, Indent, IndentStyle, NewlinePolicy, TabConversion, canonicalize, expand_tabs,
+    normalize_line_endings, unexpand_tabs,
+};
+use prepend::warning::{Strictness, WarningCode};
+use prepend::{
+    Config, dedup_targets, parse_arguments, parse_arguments_confirming, perform_prepend,
+    prepend_batch, prepend_into_file, preview_insertion, projected_size, rename_is_atomic,
+    render_header, skip_reason, streams_equal, temp_path_for, validate_file,
+    validate_metadata_only, would_change,
+};
 
 #[test]
 fn test_prepend_to_empty_file() {
@@ -15,6 +45,7 @@ fn test_prepend_to_empty_file() {
         filename: path.clone(),
         prepend_text: "Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -33,6 +64,7 @@ fn test_prepend_to_existing_content() {
         filename: path.clone(),
         prepend_text: "New Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -50,6 +82,7 @@ fn test_prepend_multiline_text() {
         filename: path.clone(),
         prepend_text: "Line 1\nLine 2\nLine 3\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -68,6 +101,7 @@ fn test_prepend_preserves_original_content() {
         filename: path.clone(),
         prepend_text: "Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -79,7 +113,7 @@ fn test_prepend_preserves_original_content() {
 #[test]
 fn test_nonexistent_file() {
     let path = PathBuf::from("/tmp/nonexistent_file_12345.txt");
-    let result = validate_file(&path);
+    let result = validate_file(&path, false);
     assert!(result.is_err());
     assert!(result.unwrap_err().to_string().contains("does not exist"));
 }
@@ -87,7 +121,7 @@ fn test_nonexistent_file() {
 #[test]
 fn test_directory_instead_of_file() {
     let dir = tempfile::tempdir().unwrap();
-    let result = validate_file(dir.path());
+    let result = validate_file(dir.path(), false);
     assert!(result.is_err());
     assert!(
         result
@@ -109,7 +143,7 @@ fn test_readonly_file() {
     perms.set_mode(0o444);
     fs::set_permissions(path, perms).unwrap();
 
-    let result = validate_file(path);
+    let result = validate_file(path, false);
     assert!(result.is_err());
     assert!(result.unwrap_err().to_string().contains("not writable"));
 }
@@ -128,6 +162,7 @@ fn test_large_file() {
         filename: path.clone(),
         prepend_text: "Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     let start = std::time::Instant::now();
@@ -156,6 +191,7 @@ fn test_binary_file() {
         filename: path.clone(),
         prepend_text: "Text Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -174,6 +210,7 @@ fn test_empty_file() {
         filename: path.clone(),
         prepend_text: "Only content\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -187,7 +224,7 @@ fn test_file_with_no_extension() {
     writeln!(file, "content").unwrap();
     let path = file.path().to_path_buf();
 
-    let result = validate_file(&path);
+    let result = validate_file(&path, false);
     assert!(result.is_ok());
 }
 
@@ -197,7 +234,7 @@ fn test_file_with_uncommon_extension() {
     let path = dir.path().join("test.xyz");
     fs::write(&path, "content\n").unwrap();
 
-    let result = validate_file(&path);
+    let result = validate_file(&path, false);
     assert!(result.is_ok());
 }
 
@@ -212,6 +249,7 @@ fn test_special_characters_in_text() {
         filename: path.clone(),
         prepend_text: special.to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -229,6 +267,7 @@ fn test_file_without_trailing_newline() {
         filename: path.clone(),
         prepend_text: "Header\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -246,6 +285,7 @@ fn test_single_character_prepend() {
         filename: path.clone(),
         prepend_text: "#\n".to_string(),
         dry_run: false,
+        ..Default::default()
     };
 
     perform_prepend(&config).unwrap();
@@ -261,9 +301,1970 @@ fn test_validate_allowed_extensions() {
         let path = dir.path().join(format!("test.{}", ext));
         fs::write(&path, "test").unwrap();
         assert!(
-            validate_file(&path).is_ok(),
+            validate_file(&path, false).is_ok(),
             "Failed for extension: {}",
             ext
         );
     }
 }
+
+#[test]
+fn test_strict_newline_rejects_missing_newline() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "content").unwrap();
+    let path = file.path().to_path_buf();
+
+    let config = Config {
+        filename: path.clone(),
+        prepend_text: "Header".to_string(),
+        strict_newline: true,
+        ..Default::default()
+    };
+
+    let result = perform_prepend(&config);
+    assert!(matches!(result, Err(PrependError::MissingTrailingNewline)));
+
+    // The file must be left untouched
+    let content = fs::read_to_string(&path).unwrap();
+    assert_eq!(content, "content\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_dedup_targets_collapses_same_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("file.txt");
+    fs::write(&path, "content\n").unwrap();
+    let link = dir.path().join("link.txt");
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    let dotted = dir.path().join(".").join("file.txt");
+
+    let targets = vec![path.clone(), dotted.clone(), link.clone()];
+    let (unique, duplicates) = dedup_targets(&targets);
+
+    assert_eq!(unique, vec![path]);
+    assert_eq!(duplicates, vec![dotted, link]);
+}
+
+#[test]
+fn test_unified_diff_for_empty_and_unterminated_files() {
+    let path = PathBuf::from("notes.txt");
+
+    let empty = unified_diff(&path, b"", b"Header\n");
+    assert_eq!(
+        String::from_utf8(empty).unwrap(),
+        "--- a/notes.txt\n+++ b/notes.txt\n@@ -0,0 +1,1 @@\n+Header\n"
+    );
+
+    let unterminated = unified_diff(&path, b"last", b"Header\n");
+    assert_eq!(
+        String::from_utf8(unterminated).unwrap(),
+        "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,1 +1,2 @@\n+Header\n last\n\\ No newline at end of file\n"
+    );
+}
+
+#[test]
+fn test_detect_encoding_and_line_endings() {
+    assert_eq!(detect_encoding(b"plain text\n"), Encoding::Utf8);
+    assert_eq!(detect_encoding(b"\xEF\xBB\xBFbom"), Encoding::Utf8Bom);
+    assert_eq!(detect_encoding(b"\xFF\xFEh\0i\0"), Encoding::Utf16Le);
+    assert_eq!(detect_encoding(&[0, 1, 2, 255]), Encoding::Binary);
+    // A prefix cut in the middle of a multi-byte character is still UTF-8
+    assert_eq!(detect_encoding(&"🦀".as_bytes()[..2]), Encoding::Utf8);
+
+    assert_eq!(detect_line_ending(b"a\nb\n"), LineEnding::Lf);
+    assert_eq!(detect_line_ending(b"a\r\nb\r\n"), LineEnding::Crlf);
+    assert_eq!(detect_line_ending(b"a\r\nb\n"), LineEnding::Mixed);
+    assert_eq!(detect_line_ending(b"no breaks"), LineEnding::None);
+}
+
+#[test]
+fn test_inspect_file_attributes() {
+    let mut utf8 = NamedTempFile::new().unwrap();
+    write!(utf8, "a\r\nb\r\nc\n").unwrap();
+    let inspection = inspect(utf8.path()).unwrap();
+    assert_eq!(inspection.size, 8);
+    assert!(!inspection.is_binary);
+    assert_eq!(inspection.encoding, Encoding::Utf8);
+    assert!(!inspection.bom);
+    assert_eq!(inspection.dominant_line_ending, LineEnding::Crlf);
+    assert!(inspection.ends_with_newline);
+
+    let mut utf16 = NamedTempFile::new().unwrap();
+    let units: Vec<u8> = "\u{feff}a\r\nb\r\n"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    utf16.write_all(&units).unwrap();
+    let inspection = inspect(utf16.path()).unwrap();
+    assert_eq!(inspection.encoding, Encoding::Utf16Le);
+    assert!(inspection.bom);
+    assert!(!inspection.is_binary);
+    assert_eq!(inspection.dominant_line_ending, LineEnding::Crlf);
+    assert!(inspection.ends_with_newline);
+
+    let mut binary = NamedTempFile::new().unwrap();
+    binary.write_all(b"\x00\x01\x02\n").unwrap();
+    let inspection = inspect(binary.path()).unwrap();
+    assert!(inspection.is_binary);
+    assert_eq!(inspection.encoding, Encoding::Binary);
+
+    let mut unterminated = NamedTempFile::new().unwrap();
+    write!(unterminated, "one\ntwo").unwrap();
+    let inspection = inspect(unterminated.path()).unwrap();
+    assert_eq!(inspection.dominant_line_ending, LineEnding::Lf);
+    assert!(!inspection.ends_with_newline);
+
+    let empty = NamedTempFile::new().unwrap();
+    let inspection = inspect(empty.path()).unwrap();
+    assert_eq!(inspection.size, 0);
+    assert_eq!(inspection.dominant_line_ending, LineEnding::None);
+    assert!(!inspection.ends_with_newline);
+
+    assert_eq!(dominant_line_ending(b"a\r\nb\n"), LineEnding::Mixed);
+}
+
+#[test]
+fn test_resumable_continues_from_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archive.log");
+    let original: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, &original).unwrap();
+
+    let config = Config {
+        filename: path.clone(),
+        prepend_text: "Header\n".to_string(),
+        resumable: true,
+        ..Default::default()
+    };
+
+    // Simulate a run interrupted after copying part of the original, with a
+    // few bytes written past the last checkpoint
+    let copied = 70_000;
+    let mut partial = b"Header\n".to_vec();
+    partial.extend_from_slice(&original[..copied + 123]);
+    fs::write(temp_path_for(&path), &partial).unwrap();
+    Checkpoint::capture(&config, copied as u64)
+        .unwrap()
+        .save(&checkpoint_path(&config))
+        .unwrap();
+
+    perform_prepend(&config).unwrap();
+
+    let content = fs::read(&path).unwrap();
+    assert_eq!(&content[..7], b"Header\n");
+    assert_eq!(&content[7..], &original[..]);
+    assert!(!temp_path_for(&path).exists());
+    assert!(!checkpoint_path(&config).exists());
+}
+
+#[test]
+fn test_resumable_ignores_stale_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archive.log");
+    fs::write(&path, "Original content\n").unwrap();
+
+    let config = Config {
+        filename: path.clone(),
+        prepend_text: "Header\n".to_string(),
+        resumable: true,
+        ..Default::default()
+    };
+
+    // A checkpoint left by a run with a different header must not be trusted
+    let stale = Config {
+        prepend_text: "Other\n".to_string(),
+        ..config.clone()
+    };
+    fs::write(temp_path_for(&path), "Other\nOrig").unwrap();
+    Checkpoint::capture(&stale, 4)
+        .unwrap()
+        .save(&checkpoint_path(&config))
+        .unwrap();
+
+    perform_prepend(&config).unwrap();
+
+    let content = fs::read_to_string(&path).unwrap();
+    assert_eq!(content, "Header\nOriginal content\n");
+}
+
+#[test]
+fn test_limit_lines_writes_capped_excerpt_to_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("huge.log");
+    let original = "line 1\nline 2\nline 3\nline 4\n";
+    fs::write(&source, original).unwrap();
+    let excerpt = dir.path().join("preview.log");
+
+    let config = Config {
+        filename: source.clone(),
+        prepend_text: "Summary\n".to_string(),
+        output: Some(excerpt.clone()),
+        limit_lines: Some(2),
+        ..Default::default()
+    };
+
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&excerpt).unwrap(),
+        "Summary\nline 1\nline 2\n"
+    );
+    // The source must not be modified
+    assert_eq!(fs::read_to_string(&source).unwrap(), original);
+}
+
+#[test]
+fn test_limit_lines_counts_unterminated_last_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("short.log");
+    fs::write(&source, "only line").unwrap();
+    let excerpt = dir.path().join("preview.log");
+
+    let config = Config {
+        filename: source,
+        prepend_text: "Summary\n".to_string(),
+        output: Some(excerpt.clone()),
+        limit_lines: Some(5),
+        ..Default::default()
+    };
+
+    perform_prepend(&config).unwrap();
+    assert_eq!(fs::read_to_string(&excerpt).unwrap(), "Summary\nonly line");
+}
+
+#[test]
+fn test_uncommon_extension_warning_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.xyz");
+    fs::write(&path, "content\n").unwrap();
+
+    let warnings = validate_file(&path, false).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, WarningCode::UncommonExtension);
+    assert_eq!(warnings[0].code.as_str(), "W001");
+
+    let allowed = dir.path().join("test.txt");
+    fs::write(&allowed, "content\n").unwrap();
+    assert!(validate_file(&allowed, false).unwrap().is_empty());
+}
+
+#[test]
+fn test_lock_contention_fails_or_skips() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "content").unwrap();
+    let path = file.path().to_path_buf();
+
+    // Another "process" holds the lock
+    let holder = fs::File::open(&path).unwrap();
+    holder.lock().unwrap();
+
+    for mode in [LockMode::Fail, LockMode::Skip] {
+        let config = Config {
+            filename: path.clone(),
+            prepend_text: "Header\n".to_string(),
+            lock: mode,
+            ..Default::default()
+        };
+        let result = perform_prepend(&config);
+        assert!(matches!(result, Err(PrependError::Locked(_))));
+    }
+
+    drop(holder);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "content\n");
+}
+
+#[test]
+fn test_lock_wait_serializes_concurrent_prepends() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("shared.log");
+    fs::write(&path, "original\n").unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let config = Config {
+                filename: path.clone(),
+                prepend_text: format!("header {}\n", i),
+                lock: LockMode::Wait,
+                ..Default::default()
+            };
+            std::thread::spawn(move || perform_prepend(&config).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // No run may lose another's header
+    let content = fs::read_to_string(&path).unwrap();
+    for i in 0..4 {
+        assert_eq!(content.matches(&format!("header {}\n", i)).count(), 1);
+    }
+    assert!(content.ends_with("original\n"));
+    assert_eq!(content.lines().count(), 5);
+}
+
+#[test]
+fn test_rename_is_atomic_for_regular_path() {
+    let file = NamedTempFile::new().unwrap();
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "Header\n".to_string(),
+        ..Default::default()
+    };
+    assert!(rename_is_atomic(&config));
+}
+
+#[test]
+fn test_indent_pads_lines_but_not_blank_ones() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "content").unwrap();
+    let path = file.path().to_path_buf();
+
+    let config = Config {
+        filename: path.clone(),
+        prepend_text: "# one\n\n# two\n".to_string(),
+        indent: Some(Indent::Fixed(4)),
+        ..Default::default()
+    };
+
+    perform_prepend(&config).unwrap();
+    let content = fs::read_to_string(&path).unwrap();
+    assert_eq!(content, "    # one\n\n    # two\ncontent\n");
+}
+
+#[test]
+fn test_indent_tabs_and_match_first_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.yaml");
+    fs::write(&path, "  \tkey: value\n").unwrap();
+
+    let tabs = Config {
+        filename: path.clone(),
+        prepend_text: "a\nb\n".to_string(),
+        indent: Some(Indent::Fixed(2)),
+        indent_style: IndentStyle::Tabs,
+        ..Default::default()
+    };
+    assert_eq!(render_header(&tabs).unwrap(), "\t\ta\n\t\tb\n");
+
+    let matched = Config {
+        indent: Some(Indent::MatchFirstLine),
+        ..tabs
+    };
+    assert_eq!(render_header(&matched).unwrap(), "  \ta\n  \tb\n");
+}
+
+#[test]
+fn test_validate_metadata_only() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let missing = dir.path().join("missing.txt");
+    assert!(matches!(
+        validate_metadata_only(&missing, false),
+        Err(PrependError::FileNotFound(_))
+    ));
+
+    assert!(matches!(
+        validate_metadata_only(dir.path(), false),
+        Err(PrependError::NotAFile(_))
+    ));
+
+    let regular = dir.path().join("regular.txt");
+    fs::write(&regular, "content\n").unwrap();
+    assert!(validate_metadata_only(&regular, false).unwrap().is_empty());
+
+    let uncommon = dir.path().join("regular.xyz");
+    fs::write(&uncommon, "content\n").unwrap();
+    assert_eq!(validate_metadata_only(&uncommon, false).unwrap().len(), 1);
+
+    let mut perms = fs::metadata(&regular).unwrap().permissions();
+    perms.set_readonly(true);
+    fs::set_permissions(&regular, perms).unwrap();
+    assert!(matches!(
+        validate_metadata_only(&regular, false),
+        Err(PrependError::NotWritable(_))
+    ));
+}
+
+#[test]
+fn test_expand_tabs_is_column_aware() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "body\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "#\tname:\tvalue\n\t\tnested\n".to_string(),
+        tabs: Some(TabConversion::Expand(4)),
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+
+    // Tabs stop at columns 4 and 8, not a fixed four spaces each
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "#   name:   value\n        nested\nbody\n"
+    );
+}
+
+#[test]
+fn test_unexpand_only_touches_indentation() {
+    assert_eq!(
+        unexpand_tabs("          a  b\n   \n \tc\n", 8),
+        "\t  a  b\n   \n\tc\n"
+    );
+    assert_eq!(expand_tabs(&unexpand_tabs("    x\n", 4), 4), "    x\n");
+}
+
+#[test]
+fn test_canonicalize_is_idempotent() {
+    let messy = "\r\n    // Header  \r\n\t\n      // indented\t\n    // end\n\n\n";
+    let once = canonicalize(messy);
+    assert_eq!(once, "// Header\n\n  // indented\n// end\n");
+    assert_eq!(canonicalize(&once), once);
+
+    assert_eq!(canonicalize("no newline"), "no newline\n");
+    assert_eq!(canonicalize(" \n\t\n"), "");
+}
+
+#[test]
+fn test_canonicalize_header_before_indent() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "body\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "  # a   \r\n  # b\n\n".to_string(),
+        canonicalize: true,
+        indent: Some(Indent::Fixed(1)),
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        " # a\n # b\nbody\n"
+    );
+}
+
+#[test]
+fn test_before_pattern_inserts_before_first_match() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "// module doc\r\nimport a\r\nimport b\r\n",
+    )
+    .unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "import first\r\n".to_string(),
+        position: InsertPosition::BeforePattern(regex::bytes::Regex::new("^import").unwrap()),
+        ..Default::default()
+    };
+    let warnings = perform_prepend(&config).unwrap().warnings;
+
+    assert!(warnings.is_empty());
+    assert_eq!(
+        fs::read(temp_file.path()).unwrap(),
+        b"// module doc\r\nimport first\r\nimport a\r\nimport b\r\n"
+    );
+}
+
+#[test]
+fn test_before_pattern_no_match_fallbacks() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "one\ntwo").unwrap();
+
+    let mut config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "inserted\n".to_string(),
+        position: InsertPosition::BeforePattern(regex::bytes::Regex::new("^missing").unwrap()),
+        no_match: NoMatch::Error,
+        ..Default::default()
+    };
+    assert!(matches!(
+        perform_prepend(&config),
+        Err(PrependError::PatternNotFound(_, _))
+    ));
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"one\ntwo");
+    assert!(!temp_path_for(temp_file.path()).exists());
+
+    config.no_match = NoMatch::Append;
+    let warnings = perform_prepend(&config).unwrap().warnings;
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, WarningCode::PatternNotMatched);
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"one\ntwo\ninserted\n");
+}
+
+#[test]
+fn test_after_pattern_preserves_line_endings() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "/* license */\r\npackage main\r\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "// inserted\n".to_string(),
+        position: InsertPosition::AfterPattern(regex::bytes::Regex::new(r"^/\*").unwrap()),
+        ..Default::default()
+    };
+    assert!(perform_prepend(&config).unwrap().warnings.is_empty());
+
+    assert_eq!(
+        fs::read(temp_file.path()).unwrap(),
+        b"/* license */\r\n// inserted\npackage main\r\n"
+    );
+}
+
+#[test]
+fn test_after_pattern_unterminated_match_and_no_match() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "first\nlast").unwrap();
+
+    let mut config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "inserted\n".to_string(),
+        position: InsertPosition::AfterPattern(regex::bytes::Regex::new("^last$").unwrap()),
+        ..Default::default()
+    };
+    assert!(perform_prepend(&config).unwrap().warnings.is_empty());
+    assert_eq!(
+        fs::read(temp_file.path()).unwrap(),
+        b"first\nlast\ninserted\n"
+    );
+
+    config.position = InsertPosition::AfterPattern(regex::bytes::Regex::new("^none$").unwrap());
+    config.no_match = NoMatch::Error;
+    assert!(matches!(
+        perform_prepend(&config),
+        Err(PrependError::PatternNotFound(_, _))
+    ));
+    assert_eq!(
+        fs::read(temp_file.path()).unwrap(),
+        b"first\nlast\ninserted\n"
+    );
+}
+
+#[test]
+fn test_under_section_inserts_after_existing_header() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "global = 1\n[client]\nname = a\n  [ server ]\nport = 80\n",
+    )
+    .unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "host = example.org\n".to_string(),
+        position: InsertPosition::UnderSection("server".to_string()),
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "global = 1\n[client]\nname = a\n  [ server ]\nhost = example.org\nport = 80\n"
+    );
+}
+
+#[test]
+fn test_under_section_creates_missing_section() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "global = 1\n[client]\nname = a\n").unwrap();
+
+    let mut config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "port = 80\n".to_string(),
+        position: InsertPosition::UnderSection("server".to_string()),
+        ..Default::default()
+    };
+    // The created section header is previewed as added, not as context
+    let preview = preview_insertion(&config, 1).unwrap();
+    assert_eq!(preview.before, ["global = 1"]);
+    assert_eq!(preview.inserted, ["[server]", "port = 80"]);
+    assert_eq!(preview.after, ["[client]"]);
+    assert!(perform_prepend(&config).unwrap().warnings.is_empty());
+    // Created above the first section, so `global` stays outside it
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "global = 1\n[server]\nport = 80\n[client]\nname = a\n"
+    );
+
+    fs::write(temp_file.path(), "global = 1").unwrap();
+    config.prepend_text = "a = b\n".to_string();
+    assert_eq!(projected_size(&config).unwrap(), 26);
+    perform_prepend(&config).unwrap();
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "global = 1\n[server]\na = b\n"
+    );
+}
+
+#[test]
+fn test_preview_insertion_keeps_context_window() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "1\n2\n3\n4\nmark\n5\n6\n7").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "a\r\nb\n".to_string(),
+        position: InsertPosition::AfterPattern(regex::bytes::Regex::new("^mark$").unwrap()),
+        ..Default::default()
+    };
+    let preview = preview_insertion(&config, 2).unwrap();
+
+    assert_eq!(preview.before, ["4", "mark"]);
+    assert_eq!(preview.inserted, ["a", "b"]);
+    assert_eq!(preview.after, ["5", "6"]);
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "1\n2\n3\n4\nmark\n5\n6\n7"
+    );
+}
+
+#[test]
+fn test_prepend_into_open_file() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(b"line 1\nline 2\n").unwrap();
+    // The handle is positioned at the end; the whole file must still be copied
+    let path = temp_file.path().to_path_buf();
+
+    let stats = prepend_into_file(temp_file.as_file_mut(), &path, b"header\n").unwrap();
+
+    assert_eq!(stats.original_size, 14);
+    assert_eq!(stats.new_size, 21);
+    assert!(stats.warnings.is_empty());
+    assert_eq!(fs::read(&path).unwrap(), b"header\nline 1\nline 2\n");
+    assert!(!temp_path_for(&path).exists());
+}
+
+#[test]
+fn test_sizes_around_buffer_boundaries_copy_exactly() {
+    let sizes = [
+        BUFFER_SIZE - 1,
+        BUFFER_SIZE,
+        BUFFER_SIZE + 1,
+        2 * BUFFER_SIZE,
+        3 * BUFFER_SIZE + 1,
+    ];
+    for size in sizes {
+        // No two neighbouring buffers look alike, so a duplicated or
+        // dropped chunk can't go unnoticed
+        let original: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        // The resumable copy has its own chunked loop
+        for resumable in [false, true] {
+            let temp_file = NamedTempFile::new().unwrap();
+            fs::write(temp_file.path(), &original).unwrap();
+
+            let config = Config {
+                filename: temp_file.path().to_path_buf(),
+                prepend_text: "header\n".to_string(),
+                resumable,
+                ..Default::default()
+            };
+            let stats = perform_prepend(&config).unwrap();
+
+            let result = fs::read(temp_file.path()).unwrap();
+            let case = format!("size {}, resumable {}", size, resumable);
+            assert_eq!(stats.new_size, (size + 7) as u64, "{}", case);
+            assert_eq!(&result[..7], b"header\n", "{}", case);
+            assert!(result[7..] == original[..], "{}", case);
+        }
+    }
+}
+
+#[test]
+fn test_newline_policies() {
+    let cases: [(NewlinePolicy, &str, &[u8]); 4] = [
+        (NewlinePolicy::Preserve, "head", b"headbody"),
+        (NewlinePolicy::Auto, "head", b"head\nbody"),
+        (NewlinePolicy::Force, "head", b"head\nbody\n"),
+        (NewlinePolicy::None, "head\r\n\n", b"headbody"),
+    ];
+    for (newline, text, expected) in cases {
+        let temp_file = NamedTempFile::new().unwrap();
+        fs::write(temp_file.path(), "body").unwrap();
+
+        let config = Config {
+            filename: temp_file.path().to_path_buf(),
+            prepend_text: text.to_string(),
+            newline,
+            ..Default::default()
+        };
+        let stats = perform_prepend(&config).unwrap();
+
+        assert_eq!(
+            fs::read(temp_file.path()).unwrap(),
+            expected,
+            "{:?}",
+            newline
+        );
+        assert_eq!(stats.new_size, expected.len() as u64, "{:?}", newline);
+    }
+}
+
+#[test]
+fn test_newline_force_with_pattern_and_terminated_file() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "a\nb\n").unwrap();
+
+    let mut config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "x".to_string(),
+        newline: NewlinePolicy::Force,
+        position: InsertPosition::AfterPattern(regex::bytes::Regex::new("^b$").unwrap()),
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+    // The final newline comes from the text; none is added twice
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"a\nb\nx\n");
+
+    // An unmatched pattern appends; NewlinePolicy::None then leaves no final newline
+    config.newline = NewlinePolicy::None;
+    config.position = InsertPosition::AfterPattern(regex::bytes::Regex::new("^none$").unwrap());
+    perform_prepend(&config).unwrap();
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"a\nb\nx\nx");
+}
+
+#[test]
+fn test_newline_conflicting_options() {
+    let args = |extra: &[&str]| -> Vec<String> {
+        ["prepend"]
+            .iter()
+            .chain(extra)
+            .chain(&["file.txt", "text"])
+            .map(|s| s.to_string())
+            .collect()
+    };
+
+    for extra in [
+        &["--raw", "--newline", "auto"][..],
+        &["--raw", "--canonicalize-header"],
+        &["--raw", "--indent", "4"],
+        &["--raw", "--indent-match"],
+        &["--raw", "--expand-tabs", "4"],
+        &["--strict-newline", "--no-newline"],
+        &["--resumable", "--newline", "force"],
+    ] {
+        assert!(
+            matches!(
+                parse_arguments(&args(extra)),
+                Err(PrependError::ConflictingOptions(_, _))
+            ),
+            "{:?}",
+            extra
+        );
+    }
+    assert_eq!(
+        parse_arguments(&args(&["--raw"])).unwrap().newline,
+        NewlinePolicy::Preserve
+    );
+    assert_eq!(
+        parse_arguments(&args(&[])).unwrap().newline,
+        NewlinePolicy::Auto
+    );
+}
+
+#[test]
+fn test_skip_empty_in_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    let empty = dir.path().join("empty.txt");
+    fs::write(&empty, "").unwrap();
+
+    let config = Config {
+        filename: empty.clone(),
+        prepend_text: "Header\n".to_string(),
+        targets: vec![empty.clone()],
+        skip_empty: true,
+        ..Default::default()
+    };
+    assert_eq!(skip_reason(&config), Some("empty"));
+    let batch = prepend_batch(&config);
+
+    assert!(batch.results.is_empty());
+    assert_eq!(batch.skipped, [empty.as_path()]);
+    assert_eq!(fs::read(&empty).unwrap(), b"");
+}
+
+#[test]
+fn test_present_match_modes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let header = "// Generated 2025-02-01\n// Do not edit\n";
+    let config = |present_match| Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: header.to_string(),
+        skip_if_present: true,
+        present_match,
+        ..Default::default()
+    };
+
+    // Exact copy: present in every mode
+    fs::write(temp_file.path(), format!("{}body\n", header)).unwrap();
+    for mode in [
+        PresentMatch::Exact,
+        PresentMatch::Trimmed,
+        PresentMatch::IgnoreFirstLine,
+    ] {
+        assert!(!needs_prepend(&config(mode)).unwrap(), "{:?}", mode);
+    }
+
+    // Trailing whitespace and CRLF: only a trimmed comparison accepts it
+    fs::write(
+        temp_file.path(),
+        "// Generated 2025-02-01  \r\n// Do not edit\r\nbody\n",
+    )
+    .unwrap();
+    assert!(needs_prepend(&config(PresentMatch::Exact)).unwrap());
+    assert!(!needs_prepend(&config(PresentMatch::Trimmed)).unwrap());
+    assert!(needs_prepend(&config(PresentMatch::IgnoreFirstLine)).unwrap());
+
+    // An older date stamp: only ignore-first-line accepts it
+    fs::write(
+        temp_file.path(),
+        "// Generated 2024-12-31\n// Do not edit\nbody\n",
+    )
+    .unwrap();
+    assert!(needs_prepend(&config(PresentMatch::Exact)).unwrap());
+    assert!(needs_prepend(&config(PresentMatch::Trimmed)).unwrap());
+    assert!(!needs_prepend(&config(PresentMatch::IgnoreFirstLine)).unwrap());
+
+    // The batch skips it instead of prepending a second copy
+    let batch = prepend_batch(&Config {
+        targets: vec![temp_file.path().to_path_buf()],
+        ..config(PresentMatch::IgnoreFirstLine)
+    });
+    assert!(batch.results.is_empty());
+    assert_eq!(batch.skipped, [temp_file.path().to_path_buf()]);
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "// Generated 2024-12-31\n// Do not edit\nbody\n"
+    );
+}
+
+#[test]
+fn test_ignore_first_line_with_one_line_header() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "unrelated\ncontent\n").unwrap();
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "// Generated 2025-02-01\n".to_string(),
+        skip_if_present: true,
+        present_match: PresentMatch::IgnoreFirstLine,
+        ..Default::default()
+    };
+
+    // With no lines after the first, only an exact copy counts
+    assert!(needs_prepend(&config).unwrap());
+    fs::write(temp_file.path(), "// Generated 2025-02-01\ncontent\n").unwrap();
+    assert!(!needs_prepend(&config).unwrap());
+}
+
+#[test]
+fn test_streams_equal() {
+    let long: Vec<u8> = (0..3 * BUFFER_SIZE + 5).map(|i| (i % 251) as u8).collect();
+
+    assert!(streams_equal(&long[..], &long[..]).unwrap());
+    assert!(streams_equal(&b""[..], &b""[..]).unwrap());
+    assert!(!streams_equal(&long[..], &long[..long.len() - 1]).unwrap());
+    assert!(!streams_equal(&b""[..], &b"x"[..]).unwrap());
+
+    // Diverging in the first chunk must not read on into the failing tail
+    struct Failing;
+    impl std::io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("read past the first chunk"))
+        }
+    }
+    let mut other = long.clone();
+    other[10] ^= 1;
+    let a = std::io::Read::chain(&long[..BUFFER_SIZE], Failing);
+    let b = std::io::Read::chain(&other[..BUFFER_SIZE], Failing);
+    assert!(!streams_equal(a, b).unwrap());
+}
+
+#[test]
+fn test_prepend_stats_sizes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "0123456789").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "abc\n".to_string(),
+        ..Default::default()
+    };
+    let stats = perform_prepend(&config).unwrap();
+
+    assert_eq!(stats.original_size, 10);
+    assert_eq!(stats.new_size, 14);
+    assert!(stats.warnings.is_empty());
+}
+
+fn batch_with_failure_in_middle(dir: &std::path::Path) -> Config {
+    let first = dir.join("first.txt");
+    let last = dir.join("last.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&last, "three\n").unwrap();
+
+    Config {
+        filename: first.clone(),
+        prepend_text: "H\n".to_string(),
+        targets: vec![first, dir.join("missing.txt"), last],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_template_values_format_utc() {
+    let values = TemplateValues::at(UNIX_EPOCH + Duration::from_secs(1_738_324_800 + 3_723));
+
+    assert_eq!(values.date, "2025-01-31");
+    assert_eq!(values.year, "2025");
+    assert_eq!(values.timestamp, "2025-01-31T13:02:03Z");
+    assert_eq!(
+        values.expand("(c) {year}, {date} {unknown}"),
+        "(c) 2025, 2025-01-31 {unknown}"
+    );
+}
+
+#[test]
+fn test_replace_regex_capture_groups_and_path_variables() {
+    let rename: Replacement = r"@module\((\w+)\)=mod $1 in {stem}".parse().unwrap();
+    assert_eq!(
+        rename.apply("// @module(net)\n", Path::new("src/http.rs")),
+        "// mod net in http\n"
+    );
+
+    // `\=` keeps an `=` in the pattern; `$` in a path stays literal
+    let equals: Replacement = r"a\==[{filename}|{dir}]".parse().unwrap();
+    assert_eq!(
+        equals.apply("a=b", Path::new("cost$1.txt")),
+        "[cost$1.txt|.]b"
+    );
+
+    assert!("no separator".parse::<Replacement>().is_err());
+    assert!("(unclosed=x".parse::<Replacement>().is_err());
+}
+
+#[test]
+fn test_replace_regex_is_evaluated_per_file_after_template() {
+    let dir = tempfile::tempdir().unwrap();
+    let alpha = dir.path().join("alpha.rs");
+    let beta = dir.path().join("beta.rs");
+    fs::write(&alpha, "fn a() {}\n").unwrap();
+    fs::write(&beta, "fn b() {}\n").unwrap();
+
+    let args: Vec<String> = [
+        "prepend",
+        "--template",
+        "--replace-regex",
+        "NAME=module {stem}",
+        "--replace-regex",
+        r"(\d{4})-\d\d-\d\d=$1",
+        alpha.to_str().unwrap(),
+        beta.to_str().unwrap(),
+        "// NAME, {date}",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let config = parse_arguments(&args).unwrap();
+
+    let batch = prepend_batch(&config);
+
+    assert_eq!(batch.failures(), 0);
+    let year = &config.template.as_ref().unwrap().year;
+    assert_eq!(
+        fs::read_to_string(&alpha).unwrap(),
+        format!("// module alpha, {}\nfn a() {{}}\n", year)
+    );
+    assert_eq!(
+        fs::read_to_string(&beta).unwrap(),
+        format!("// module beta, {}\nfn b() {{}}\n", year)
+    );
+}
+
+#[test]
+fn test_template_values_are_shared_across_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&second, "two\n").unwrap();
+
+    let args: Vec<String> = [
+        "prepend",
+        "--template",
+        first.to_str().unwrap(),
+        second.to_str().unwrap(),
+        "generated at {timestamp}",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    // Push the second file's timestamp past the first one's if it were recomputed
+    let slow_first = first.clone();
+    let config = parse_arguments(&args)
+        .unwrap()
+        .with_temp_name(move |path: &Path| {
+            if path == slow_first {
+                std::thread::sleep(Duration::from_millis(1100));
+            }
+            temp_path_for(path)
+        });
+
+    let batch = prepend_batch(&config);
+
+    assert_eq!(batch.failures(), 0);
+    let stamp = &config.template.as_ref().unwrap().timestamp;
+    assert_eq!(
+        fs::read_to_string(&first).unwrap(),
+        format!("generated at {}\none\n", stamp)
+    );
+    assert_eq!(
+        fs::read_to_string(&second).unwrap(),
+        format!("generated at {}\ntwo\n", stamp)
+    );
+}
+
+#[test]
+fn test_prepend_batch_keeps_going_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = batch_with_failure_in_middle(dir.path());
+
+    let batch = prepend_batch(&config);
+
+    assert!(!batch.aborted);
+    assert_eq!(batch.results.len(), 3);
+    assert_eq!(batch.failures(), 1);
+    assert!(matches!(
+        batch.results[1].1,
+        Err(PrependError::FileNotFound(_))
+    ));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("last.txt")).unwrap(),
+        "H\nthree\n"
+    );
+}
+
+#[test]
+fn test_prepend_batch_fail_fast_stops_at_first_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        fail_fast: true,
+        ..batch_with_failure_in_middle(dir.path())
+    };
+
+    let batch = prepend_batch(&config);
+
+    assert!(batch.aborted);
+    assert_eq!(batch.results.len(), 2);
+    assert!(batch.results[0].1.is_ok());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("last.txt")).unwrap(),
+        "three\n"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_fast_temp_removes_scratch_when_copy_fails() {
+    // A directory opens fine on Linux, but reading it fails mid-copy
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("data.txt");
+    fs::create_dir(&target).unwrap();
+    let name = temp_path_for(&target).file_name().unwrap().to_owned();
+    let scratch =
+        std::env::temp_dir().join(format!("{}.{}", std::process::id(), name.to_string_lossy()));
+
+    let result = perform_prepend(&Config {
+        filename: target.clone(),
+        prepend_text: "H\n".to_string(),
+        fast_temp: true,
+        ..Default::default()
+    });
+
+    assert!(matches!(result, Err(PrependError::Io(_))));
+    assert!(!scratch.exists());
+    assert!(!temp_path_for(&target).exists());
+}
+
+#[test]
+fn test_prepend_batch_reports_duplicates_as_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.txt");
+    fs::write(&path, "body\n").unwrap();
+    let alias = dir.path().join(".").join("data.txt");
+
+    let batch = prepend_batch(&Config {
+        prepend_text: "H\n".to_string(),
+        targets: vec![path.clone(), alias.clone()],
+        ..Default::default()
+    });
+
+    assert_eq!(batch.results.len(), 1);
+    assert_eq!(batch.skipped, [alias]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "H\nbody\n");
+}
+
+#[test]
+fn test_prepend_batch_without_targets() {
+    let batch = prepend_batch(&Config {
+        transactional: true,
+        git_tracked: true,
+        ..Default::default()
+    });
+
+    assert!(batch.results.is_empty());
+    assert!(batch.skipped.is_empty());
+}
+
+#[test]
+fn test_nul_in_text_warns_with_offset() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "body\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "abc\0def\n".to_string(),
+        ..Default::default()
+    };
+    let warnings = perform_prepend(&config).unwrap().warnings;
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, WarningCode::NulInText);
+    assert!(warnings[0].message.contains("offset 3"));
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"abc\0def\nbody\n");
+}
+
+#[test]
+fn test_strict_fails_before_writing() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "body\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "abc\0def\n".to_string(),
+        strict: Strictness::Fail,
+        ..Default::default()
+    };
+    let result = perform_prepend(&config);
+
+    assert!(matches!(
+        result,
+        Err(PrependError::WarningAsError(ref w)) if w.code == WarningCode::NulInText
+    ));
+    assert_eq!(fs::read(temp_file.path()).unwrap(), b"body\n");
+}
+
+#[test]
+fn test_projected_size_matches_result() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.txt");
+    let cases = [
+        Config {
+            prepend_text: "Header\n".to_string(),
+            ..Default::default()
+        },
+        Config {
+            prepend_text: "a\n\nb\n".to_string(),
+            indent: Some(Indent::Fixed(4)),
+            ..Default::default()
+        },
+        Config {
+            prepend_text: "Header\n".to_string(),
+            output: Some(dir.path().join("excerpt.txt")),
+            limit_lines: Some(2),
+            ..Default::default()
+        },
+        Config {
+            prepend_text: "Header\n".to_string(),
+            position: InsertPosition::AfterPattern(regex::bytes::Regex::new("^three").unwrap()),
+            ..Default::default()
+        },
+        Config {
+            prepend_text: "Header\n".to_string(),
+            newline: NewlinePolicy::Force,
+            ..Default::default()
+        },
+        Config {
+            prepend_text: "Header\n".to_string(),
+            newline: NewlinePolicy::Force,
+            position: InsertPosition::AfterPattern(regex::bytes::Regex::new("^two").unwrap()),
+            ..Default::default()
+        },
+    ];
+
+    for case in cases {
+        fs::write(&path, "one\ntwo\nthree").unwrap();
+        let config = Config {
+            filename: path.clone(),
+            ..case
+        };
+
+        let projected = projected_size(&config).unwrap();
+        perform_prepend(&config).unwrap();
+
+        assert_eq!(projected, fs::metadata(config.destination()).unwrap().len());
+    }
+}
+
+#[test]
+fn test_require_extension_rejects_uncommon_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.xyz");
+    fs::write(&path, "content\n").unwrap();
+
+    assert!(matches!(
+        validate_file(&path, true),
+        Err(PrependError::DisallowedExtension(_))
+    ));
+    assert!(matches!(
+        validate_metadata_only(&path, true),
+        Err(PrependError::DisallowedExtension(_))
+    ));
+
+    let warnings = validate_file(&path, false).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, WarningCode::UncommonExtension);
+}
+
+#[test]
+fn test_transactional_batch_restores_on_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "one\n").unwrap();
+    fs::write(&second, "two\n").unwrap();
+
+    let config = Config {
+        filename: first.clone(),
+        prepend_text: "H\n".to_string(),
+        targets: vec![
+            first.clone(),
+            second.clone(),
+            dir.path().join("missing.txt"),
+        ],
+        transactional: true,
+        ..Default::default()
+    };
+    let batch = prepend_batch(&config);
+
+    assert!(batch.aborted);
+    assert_eq!(batch.rolled_back, 2);
+    assert!(batch.results[0].1.is_ok());
+    assert!(batch.results[1].1.is_ok());
+    assert!(batch.results[2].1.is_err());
+    assert_eq!(fs::read_to_string(&first).unwrap(), "one\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "two\n");
+    assert!(!backup_path_for(&first).exists());
+    assert!(!backup_path_for(&second).exists());
+}
+
+#[test]
+fn test_transactional_batch_removes_created_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let existing = dir.path().join("existing.txt");
+    let created = dir.path().join("new.txt");
+    fs::write(&existing, "one\n").unwrap();
+
+    let config = Config {
+        filename: existing.clone(),
+        prepend_text: "H\n".to_string(),
+        targets: vec![
+            existing.clone(),
+            created.clone(),
+            dir.path().join("missing").join("other.txt"),
+        ],
+        transactional: true,
+        create: true,
+        ..Default::default()
+    };
+    let batch = prepend_batch(&config);
+
+    assert!(batch.aborted);
+    assert!(batch.results[1].1.is_ok());
+    assert!(batch.results[2].1.is_err());
+    assert_eq!(batch.rolled_back, 2);
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "one\n");
+    assert!(!created.exists());
+}
+
+#[test]
+fn test_transactional_batch_commits_on_success() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    fs::write(&first, "one\n").unwrap();
+
+    let config = Config {
+        filename: first.clone(),
+        prepend_text: "H\n".to_string(),
+        targets: vec![first.clone()],
+        transactional: true,
+        ..Default::default()
+    };
+    let batch = prepend_batch(&config);
+
+    assert!(!batch.aborted);
+    assert_eq!(batch.rolled_back, 0);
+    assert_eq!(fs::read_to_string(&first).unwrap(), "H\none\n");
+    assert!(!backup_path_for(&first).exists());
+}
+
+#[test]
+fn test_check_space_reports_shortfall() {
+    let path = PathBuf::from("big.log");
+
+    assert!(check_space(&path, 100, 100).is_ok());
+
+    let err = check_space(&path, 150, 100).unwrap_err();
+    assert!(matches!(err, PrependError::InsufficientSpace(_, 150, 100)));
+    assert!(err.to_string().contains("50 short"));
+}
+
+#[test]
+fn test_check_space_passes_for_small_file() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "body\n").unwrap();
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "Header\n".to_string(),
+        check_space: true,
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(temp_file.path()).unwrap(),
+        "Header\nbody\n"
+    );
+    if cfg!(unix) {
+        assert!(
+            available_space(temp_file.path().parent().unwrap())
+                .unwrap()
+                .unwrap()
+                > 0
+        );
+    }
+}
+
+#[test]
+fn test_custom_temp_name_is_honored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.txt");
+    fs::write(&path, "body\n").unwrap();
+
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorder = seen.clone();
+    let config = Config {
+        filename: path.clone(),
+        prepend_text: "Header\n".to_string(),
+        ..Default::default()
+    }
+    .with_temp_name(move |destination: &std::path::Path| {
+        let temp = destination.with_file_name(".data.txt.partial");
+        recorder.lock().unwrap().push(temp.clone());
+        temp
+    });
+
+    assert_eq!(config.temp_path(), dir.path().join(".data.txt.partial"));
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "Header\nbody\n");
+    assert!(
+        seen.lock()
+            .unwrap()
+            .contains(&dir.path().join(".data.txt.partial"))
+    );
+    assert!(!temp_path_for(&path).exists());
+    assert!(!dir.path().join(".data.txt.partial").exists());
+}
+
+#[test]
+fn test_recount_lines_with_and_without_trailing_newline() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let cases: [(&str, &str, Option<u64>); 4] = [
+        ("a\nb\n", "H1\nH2\n", Some(4)),
+        ("a\nb", "H1\nH2\n", Some(4)),
+        ("", "H\n", Some(1)),
+        ("a", "no newline ", Some(1)),
+    ];
+
+    for (original, text, expected) in cases {
+        fs::write(temp_file.path(), original).unwrap();
+        let config = Config {
+            filename: temp_file.path().to_path_buf(),
+            prepend_text: text.to_string(),
+            recount_lines: true,
+            ..Default::default()
+        };
+        let stats = perform_prepend(&config).unwrap();
+        assert_eq!(stats.lines, expected, "original {:?}", original);
+    }
+
+    let config = Config {
+        filename: temp_file.path().to_path_buf(),
+        prepend_text: "H\n".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(perform_prepend(&config).unwrap().lines, None);
+}
+
+#[test]
+fn test_spec_with_escaped_separators() {
+    let spec: Spec = r"file=notes.txt;text=a\;b\=c\\d".parse().unwrap();
+    assert_eq!(spec.file, PathBuf::from("notes.txt"));
+    assert_eq!(spec.text, r"a;b=c\d");
+
+    let spec: Spec = "text=first;file=dir/a=b.txt;".parse().unwrap();
+    assert_eq!(spec.file, PathBuf::from("dir/a=b.txt"));
+    assert_eq!(spec.text, "first");
+
+    for invalid in [
+        "file=a.txt",
+        "file=a.txt;text=x;mode=y",
+        r"file=a.txt;text=x\",
+    ] {
+        assert!(matches!(
+            invalid.parse::<Spec>(),
+            Err(PrependError::InvalidValue(_, _))
+        ));
+    }
+}
+
+#[test]
+fn test_parse_arguments_with_specs() {
+    let args: Vec<String> = [
+        "prepend",
+        "--spec",
+        "file=a.txt;text=for a",
+        "--spec",
+        r"file=b.txt;text=b.txt\; looks like a path",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let config = parse_arguments(&args).unwrap();
+
+    assert_eq!(
+        config.targets,
+        vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")]
+    );
+    // The newline is added when the text is rendered (NewlinePolicy::Auto)
+    assert_eq!(
+        render_header(&config.for_target(&config.targets[0])).unwrap(),
+        "for a\n"
+    );
+    assert_eq!(
+        render_header(&config.for_target(&config.targets[1])).unwrap(),
+        "b.txt; looks like a path\n"
+    );
+}
+
+#[test]
+fn test_parse_cutoff_durations_and_timestamps() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    assert_eq!(
+        parse_cutoff("2h", now).unwrap(),
+        now - Duration::from_secs(7200)
+    );
+    assert_eq!(
+        parse_cutoff("7d", now).unwrap(),
+        now - Duration::from_secs(7 * 86_400)
+    );
+
+    let epoch = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+    assert_eq!(
+        parse_cutoff("2000-01-01T00:00:00Z", now).unwrap(),
+        epoch(946_684_800)
+    );
+    assert_eq!(
+        parse_cutoff("2000-01-01T02:00:00+02:00", now).unwrap(),
+        epoch(946_684_800)
+    );
+    assert_eq!(
+        parse_cutoff("2024-02-29T12:30:15.25Z", now).unwrap(),
+        epoch(1_709_209_815) + Duration::from_millis(250)
+    );
+    assert!(parse_cutoff("1969-12-31T23:59:59Z", now).unwrap() < SystemTime::UNIX_EPOCH);
+
+    for invalid in [
+        "yesterday",
+        "7x",
+        "2000-13-01T00:00:00Z",
+        "2000-01-01T00:00:00",
+        "2024-02-31T00:00:00Z",
+        "2023-02-29T00:00:00Z",
+        "2024-04-31T00:00:00Z",
+    ] {
+        assert!(matches!(
+            parse_cutoff(invalid, now),
+            Err(PrependError::InvalidValue(_, _))
+        ));
+    }
+}
+
+#[test]
+fn test_prepend_batch_modified_since_skips_older_files() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::tempdir().unwrap();
+    let old = dir.path().join("old.txt");
+    let new = dir.path().join("new.txt");
+    fs::write(&old, "old\n").unwrap();
+    fs::write(&new, "new\n").unwrap();
+    let now = SystemTime::now();
+    fs::File::options()
+        .write(true)
+        .open(&old)
+        .unwrap()
+        .set_modified(now - Duration::from_secs(3 * 86_400))
+        .unwrap();
+
+    let config = Config {
+        filename: old.clone(),
+        prepend_text: "H\n".to_string(),
+        targets: vec![old.clone(), new.clone()],
+        modified_since: Some(now - Duration::from_secs(86_400)),
+        ..Default::default()
+    };
+    let batch = prepend_batch(&config);
+
+    assert_eq!(batch.skipped, vec![old.clone()]);
+    assert_eq!(batch.results.len(), 1);
+    assert_eq!(fs::read_to_string(&old).unwrap(), "old\n");
+    assert_eq!(fs::read_to_string(&new).unwrap(), "H\nnew\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fifo_and_device_are_special_files() {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let fifo = dir.path().join("pipe.txt");
+    let c_path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+
+    for result in [
+        validate_file(&fifo, false),
+        validate_metadata_only(&fifo, false),
+    ] {
+        match result {
+            Err(PrependError::SpecialFile(_, kind)) => assert_eq!(kind, "named pipe (FIFO)"),
+            other => panic!("expected SpecialFile, got {:?}", other),
+        }
+    }
+
+    let null = std::path::Path::new("/dev/null");
+    if null.exists() {
+        assert!(matches!(
+            validate_metadata_only(null, false),
+            Err(PrependError::SpecialFile(_, "character device"))
+        ));
+    }
+
+    // Directories keep the generic error
+    assert!(matches!(
+        validate_metadata_only(dir.path(), false),
+        Err(PrependError::NotAFile(_))
+    ));
+}
+
+#[test]
+fn test_parse_pairs() {
+    let specs = parse_pairs("a.txt\tHeader A\r\n\nb.txt\tB\twith tab\n", '\t').unwrap();
+    assert_eq!(
+        specs,
+        vec![
+            Spec {
+                file: PathBuf::from("a.txt"),
+                text: "Header A".to_string(),
+            },
+            Spec {
+                file: PathBuf::from("b.txt"),
+                text: "B\twith tab".to_string(),
+            },
+        ]
+    );
+
+    let specs = parse_pairs("c.txt|C\n", '|').unwrap();
+    assert_eq!(specs[0].file, PathBuf::from("c.txt"));
+
+    assert!(matches!(
+        parse_pairs("a.txt\tA\nno delimiter\n", '\t'),
+        Err(PrependError::MalformedPair(2, _))
+    ));
+    assert!(matches!(
+        parse_pairs("\tA\n", '\t'),
+        Err(PrependError::MalformedPair(1, _))
+    ));
+    assert!(matches!(
+        parse_pairs("\n\n", '\t'),
+        Err(PrependError::EmptyInput)
+    ));
+}
+
+#[test]
+fn test_render_manifest_csv_quotes_fields() {
+    let entries = [
+        ManifestEntry {
+            old_size: Some(10),
+            new_size: Some(16),
+            ..ManifestEntry::new(Path::new("a,b.txt"), "success")
+        },
+        ManifestEntry {
+            error: Some("File not found: \"c.txt\"".to_string()),
+            ..ManifestEntry::new(Path::new("c.txt"), "error")
+        },
+    ];
+    assert_eq!(entries[0].bytes_added(), Some(6));
+    assert_eq!(entries[1].bytes_added(), None);
+
+    let csv = render_manifest(&entries, ManifestFormat::Csv);
+    assert_eq!(
+        csv,
+        "file,status,old_size,new_size,bytes_added,backup,error\n\
+         \"a,b.txt\",success,10,16,6,,\n\
+         c.txt,error,,,,,\"File not found: \"\"c.txt\"\"\"\n"
+    );
+    assert_eq!(
+        "csv".parse::<ManifestFormat>().unwrap(),
+        ManifestFormat::Csv
+    );
+    assert!("xml".parse::<ManifestFormat>().is_err());
+}
+
+#[test]
+fn test_max_files_guards_target_collection() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(dir.path().join(name), "content\n").unwrap();
+    }
+    let pattern = dir.path().join("*.txt").to_string_lossy().into_owned();
+    let args = |cap: &str| -> Vec<String> {
+        ["prepend", "--max-files", cap, &pattern, "Header"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    };
+
+    assert!(matches!(
+        parse_arguments(&args("2")),
+        Err(PrependError::TooManyFiles(3, 2))
+    ));
+    assert_eq!(parse_arguments(&args("3")).unwrap().targets.len(), 3);
+
+    // A confirmation lets the same parse go ahead
+    let mut asked = None;
+    let config = parse_arguments_confirming(&args("2"), |found, cap| {
+        asked = Some((found, cap));
+        true
+    });
+    assert_eq!(config.unwrap().targets.len(), 3);
+    assert_eq!(asked, Some((3, 2)));
+    assert!(matches!(
+        parse_arguments_confirming(&args("2"), |_, _| false),
+        Err(PrependError::TooManyFiles(3, 2))
+    ));
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        assert_eq!(
+            fs::read_to_string(dir.path().join(name)).unwrap(),
+            "content\n"
+        );
+    }
+}
+
+#[test]
+fn test_interactive_reader_multi_line_input() {
+    let mut prompt = Vec::new();
+    let text = InteractiveReader::new(&b"first\nsecond\n"[..], &mut prompt)
+        .run(&[PathBuf::from("notes.txt")])
+        .unwrap();
+    assert_eq!(text, "first\nsecond\n");
+
+    let prompt = String::from_utf8(prompt).unwrap();
+    assert!(prompt.contains("Ready to process \"notes.txt\""));
+    assert!(prompt.contains("Ctrl+D"));
+}
+
+#[test]
+fn test_interactive_reader_empty_input() {
+    for input in [&b""[..], &b"  \n\t\n"[..]] {
+        let mut prompt = Vec::new();
+        let result = InteractiveReader::new(input, &mut prompt)
+            .run(&[PathBuf::from("a.txt"), PathBuf::from("b.txt")]);
+        assert!(matches!(result, Err(PrependError::EmptyInput)));
+        // The prompt was still shown, before anything was read
+        assert!(
+            String::from_utf8(prompt)
+                .unwrap()
+                .contains("Ready to process 2 files")
+        );
+    }
+}
+
+#[test]
+fn test_interactive_reader_input_without_trailing_newline() {
+    let text = InteractiveReader::new(&b"last line"[..], std::io::sink())
+        .run(&[PathBuf::from("a.txt")])
+        .unwrap();
+    // Read verbatim; the default newline policy adds the newline when rendering
+    assert_eq!(text, "last line");
+    assert_eq!(NewlinePolicy::Auto.apply_to_text(&text), "last line\n");
+
+    // Invalid UTF-8 fails unless lossy
+    let invalid = &b"caf\xe9"[..];
+    assert!(
+        InteractiveReader::new(invalid, std::io::sink())
+            .run(&[PathBuf::from("a.txt")])
+            .is_err()
+    );
+    let text = InteractiveReader::new(invalid, std::io::sink())
+        .lossy(true)
+        .run(&[PathBuf::from("a.txt")])
+        .unwrap();
+    assert_eq!(text, "caf\u{FFFD}");
+}
+
+/// Marks the header with the target's line ending and redacts secrets
+struct Audit;
+
+impl ContentTransform for Audit {
+    fn transform_header(&self, header: &[u8], context: &FileContext) -> Vec<u8> {
+        let tag = format!("[{}] ", context.line_ending());
+        [tag.as_bytes(), header].concat()
+    }
+
+    fn transform_line(&self, line: &[u8]) -> Option<Vec<u8>> {
+        line.starts_with(b"password=")
+            .then(|| b"password=***".to_vec())
+    }
+}
+
+#[test]
+fn test_custom_content_transform() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "user=me\r\npassword=hunter2\r\n").unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "audited\n".to_string(),
+        ..Default::default()
+    }
+    .with_transform(Audit);
+    assert_eq!(render_header(&config).unwrap(), "[crlf] audited\n");
+
+    let stats = perform_prepend(&config).unwrap();
+
+    let expected = "[crlf] audited\nuser=me\r\npassword=***\r\n";
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), expected);
+    assert_eq!(stats.new_size, expected.len() as u64);
+}
+
+#[test]
+fn test_custom_line_transform_with_section_insert() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "[main]\npassword=x\n").unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "key=1\n".to_string(),
+        position: InsertPosition::UnderSection("main".to_string()),
+        ..Default::default()
+    }
+    .with_transform(Audit);
+    assert_eq!(projected_size(&config).unwrap(), 31);
+
+    perform_prepend(&config).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        "[main]\n[lf] key=1\npassword=***\n"
+    );
+}
+
+#[test]
+fn test_tab_conversion_is_a_content_transform() {
+    let context = FileContext::new(Path::new("missing.txt"));
+    assert_eq!(
+        TabConversion::Expand(4).transform_header(b"\tx\n", &context),
+        b"    x\n"
+    );
+    // Nothing to read, so the target counts as empty
+    assert_eq!(context.encoding(), Encoding::Utf8);
+    assert_eq!(context.line_ending(), LineEnding::None);
+
+    let config = Config {
+        filename: PathBuf::from("missing.txt"),
+        resumable: true,
+        ..Default::default()
+    }
+    .with_transform(Audit);
+    assert!(matches!(
+        perform_prepend(&config),
+        Err(PrependError::ConflictingOptions(_, _))
+    ));
+}
+
+#[test]
+fn test_would_change_compares_projected_result() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source.txt");
+    let output = dir.path().join("output.txt");
+    fs::write(&source, "body\n").unwrap();
+
+    let config = Config {
+        filename: source.clone(),
+        prepend_text: "Header\n".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    // No destination yet
+    assert!(would_change(&config).unwrap());
+
+    // Same size, different content
+    fs::write(&output, "Heade!\nbody\n").unwrap();
+    assert!(would_change(&config).unwrap());
+
+    perform_prepend(&config).unwrap();
+    assert!(!would_change(&config).unwrap());
+
+    // Only --newline force would still add a final newline
+    fs::write(&source, "body").unwrap();
+    fs::write(&output, "Header\nbody").unwrap();
+    assert!(!would_change(&config).unwrap());
+    let forced = Config {
+        newline: NewlinePolicy::Force,
+        ..config
+    };
+    assert!(would_change(&forced).unwrap());
+}
+
+#[test]
+fn test_normalize_eol_rewrites_whole_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "one\r\ntwo\nthree\r\r\nprogress\rdone\nlast").unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "Header\r\nline 2\n".to_string(),
+        normalize_eol: Some(EolStyle::Crlf),
+        ..Default::default()
+    };
+    let expected = "Header\r\nline 2\r\none\r\ntwo\r\nthree\r\r\nprogress\rdone\r\nlast";
+    assert_eq!(projected_size(&config).unwrap(), expected.len() as u64);
+
+    let stats = perform_prepend(&config).unwrap();
+
+    assert!(stats.warnings.is_empty());
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), expected);
+    assert_eq!(
+        normalize_line_endings("a\r\nb\r\r\nc\r", EolStyle::Lf),
+        "a\nb\r\nc\r"
+    );
+}
+
+#[test]
+fn test_normalize_eol_leaves_binary_alone() {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(b"\x00\x01\r\n\x02\n").unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "H\r\n".to_string(),
+        normalize_eol: Some(EolStyle::Lf),
+        ..Default::default()
+    };
+    let stats = perform_prepend(&config).unwrap();
+
+    assert_eq!(stats.warnings[0].code, WarningCode::EolNotNormalized);
+    assert_eq!(fs::read(file.path()).unwrap(), b"H\r\n\x00\x01\r\n\x02\n");
+
+    fs::write(file.path(), b"\x00\r\n").unwrap();
+    let forced = Config {
+        force: true,
+        ..config
+    };
+    let stats = perform_prepend(&forced).unwrap();
+
+    assert!(stats.warnings.is_empty());
+    assert_eq!(fs::read(file.path()).unwrap(), b"H\n\x00\n");
+}
+
+#[test]
+fn test_hash_marker_skips_or_replaces_managed_block() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "body").unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "# Copyright 2025\n".to_string(),
+        hash_marker: Some("#".to_string()),
+        ..Default::default()
+    };
+    perform_prepend(&config).unwrap();
+
+    let hash = header_hash(b"# Copyright 2025\n");
+    let block = format!("# Copyright 2025\n# prepend-hash: {}\n", hash);
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        format!("{}body\n", block)
+    );
+    assert_eq!(
+        find_block(file.path(), "#").unwrap(),
+        Some(ManagedBlock {
+            len: block.len() as u64,
+            hash: hash.clone(),
+        })
+    );
+    assert_eq!(skip_reason(&config), Some("unchanged"));
+    // Marking is idempotent, so rendering the text again adds nothing
+    assert_eq!(mark(&block, "#"), block);
+
+    let changed = Config {
+        prepend_text: "# Copyright 2026\n".to_string(),
+        ..config
+    };
+    assert_eq!(skip_reason(&changed), None);
+    assert_ne!(header_hash(b"# Copyright 2026\n"), hash);
+    let expected = format!(
+        "# Copyright 2026\n# prepend-hash: {}\nbody\n",
+        header_hash(b"# Copyright 2026\n")
+    );
+    assert_eq!(projected_size(&changed).unwrap(), expected.len() as u64);
+
+    perform_prepend(&changed).unwrap();
+
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), expected);
+}
+
+#[test]
+fn test_hash_marker_ignores_line_with_wrong_hash() {
+    let mut file = NamedTempFile::new().unwrap();
+    let original = "keep me\nand me\n# prepend-hash: 0123456789abcdef\nbody\n";
+    file.write_all(original.as_bytes()).unwrap();
+
+    let config = Config {
+        filename: file.path().to_path_buf(),
+        prepend_text: "# Copyright 2025\n".to_string(),
+        hash_marker: Some("#".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(find_block(file.path(), "#").unwrap(), None);
+    perform_prepend(&config).unwrap();
+
+    let block = format!(
+        "# Copyright 2025\n# prepend-hash: {}\n",
+        header_hash(b"# Copyright 2025\n")
+    );
+    assert_eq!(
+        fs::read_to_string(file.path()).unwrap(),
+        format!("{}{}", block, original)
+    );
+}